@@ -0,0 +1,508 @@
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use actix_web::dev::Payload;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Result;
+use jsonschema::Validator;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::inspector::SchemaInspector;
+use crate::redact::{redact, redact_report, RedactionOptions};
+use crate::registry::SchemaRegistry;
+use crate::report::ValidationReport;
+use crate::typed::{deserialize, TypedSchema, ValidateIntoError};
+
+/// The media type of RFC 7807 problem details.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// A JSON request body that has been validated against its type's schema and deserialized.
+///
+/// The schema is looked up by type in the `ValidatedJsonConfig` registered as app data, either
+/// directly or as `web::Data`. The body is read with actix's own `Json` extractor, so its
+/// `JsonConfig` size limit and content type checks still apply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ValidatedJson<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for ValidatedJson<T> {
+    type Error = ValidatedJsonError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let body = web::Json::<Value>::from_request(&req, payload);
+
+        Box::pin(async move {
+            let config = req
+                .app_data::<ValidatedJsonConfig>()
+                .or_else(|| {
+                    req.app_data::<web::Data<ValidatedJsonConfig>>()
+                        .map(|data| data.get_ref())
+                })
+                .ok_or(ValidatedJsonError::MissingSchema(type_name::<T>()))?;
+            let schema = config
+                .schemas
+                .get(&TypeId::of::<T>())
+                .ok_or(ValidatedJsonError::MissingSchema(type_name::<T>()))?;
+
+            let body = body.await.map_err(ValidatedJsonError::Payload)?;
+            schema
+                .extract(&body, &config.redaction)
+                .map(ValidatedJson)
+                .map_err(ValidatedJsonError::Rejected)
+        })
+    }
+}
+
+/// The schemas `ValidatedJson` validates request bodies against, by body type.
+///
+/// Schemas are compiled when they are added, resolving references against the configured
+/// registry, so `with_registry` must come before any schemas that refer into it.
+#[derive(Clone, Default)]
+pub struct ValidatedJsonConfig {
+    registry: SchemaRegistry,
+    schemas: HashMap<TypeId, Arc<RequestSchema>>,
+    redaction: RedactionOptions,
+}
+
+impl ValidatedJsonConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the registry schemas' references are resolved against, and `with_schema_uri` looks
+    /// schemas up in.
+    pub fn with_registry(mut self, registry: SchemaRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Sets how sensitive values are redacted from error responses.
+    pub fn with_redaction(mut self, redaction: RedactionOptions) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    /// Validates bodies of type `T` against the schema.
+    pub fn with_schema<T: 'static>(mut self, schema: &Value) -> Result<Self> {
+        let request_schema = RequestSchema {
+            validator: self.registry.validator_for(schema)?,
+            inspector: SchemaInspector::with_registry(schema, &self.registry)?,
+        };
+        self.schemas
+            .insert(TypeId::of::<T>(), Arc::new(request_schema));
+
+        Ok(self)
+    }
+
+    /// Validates bodies of type `T` against the schema registered under the URI.
+    pub fn with_schema_uri<T: 'static>(mut self, uri: &str) -> Result<Self> {
+        let request_schema = RequestSchema {
+            validator: self.registry.validator_for_uri(uri)?,
+            inspector: SchemaInspector::for_uri(&self.registry, uri)?,
+        };
+        self.schemas
+            .insert(TypeId::of::<T>(), Arc::new(request_schema));
+
+        Ok(self)
+    }
+
+    /// Validates bodies of type `T` against the schema the type provides.
+    pub fn with_typed_schema<T: TypedSchema + 'static>(self) -> Result<Self> {
+        self.with_schema::<T>(&T::json_schema())
+    }
+
+    /// Validates bodies of type `T` against the draft 2020-12 schema generated for it.
+    #[cfg(feature = "derive")]
+    pub fn with_derived_schema<T: schemars::JsonSchema + 'static>(self) -> Result<Self> {
+        self.with_schema::<T>(&crate::derive::schema_for::<T>(Default::default()))
+    }
+}
+
+struct RequestSchema {
+    validator: Validator,
+    inspector: SchemaInspector,
+}
+
+impl RequestSchema {
+    fn extract<T: DeserializeOwned>(
+        &self,
+        body: &Value,
+        redaction: &RedactionOptions,
+    ) -> Result<T, ValidateIntoError> {
+        let report = ValidationReport::collect(&self.validator, body, None);
+        if !report.is_valid() {
+            let report = redact_report(&self.inspector, body, &report, redaction)
+                .map_err(|err| ValidateIntoError::InvalidSchema(err.to_string()))?;
+            return Err(ValidateIntoError::Validation(report));
+        }
+
+        deserialize(body).map_err(|err| match err {
+            ValidateIntoError::Deserialization { instance_path, .. }
+                if self.is_sensitive(body, &instance_path, redaction) =>
+            {
+                ValidateIntoError::Deserialization {
+                    instance_path,
+                    message: "the value does not fit the expected type".to_string(),
+                }
+            }
+            err => err,
+        })
+    }
+
+    /// Whether serde's message about the value at the pointer might quote a sensitive value.
+    fn is_sensitive(&self, body: &Value, pointer: &str, redaction: &RedactionOptions) -> bool {
+        redact(&self.inspector, body, redaction).map_or(true, |redaction| {
+            redaction.redacted.iter().any(|path| {
+                pointer == path
+                    || pointer.starts_with(&format!("{path}/"))
+                    || path.starts_with(&format!("{pointer}/"))
+            })
+        })
+    }
+}
+
+/// Why a `ValidatedJson` body was rejected. Responds with RFC 7807 problem details.
+#[derive(Debug)]
+pub enum ValidatedJsonError {
+    /// The body couldn't be read as JSON.
+    Payload(actix_web::Error),
+    /// No schema is configured for the body type, which is a server error.
+    MissingSchema(&'static str),
+    /// The body failed validation or deserialization, with any sensitive values redacted.
+    Rejected(ValidateIntoError),
+}
+
+/// An RFC 7807 problem details body.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Each failing value in the request body.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ProblemError>,
+}
+
+/// A failing value listed in `ProblemDetails`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemError {
+    /// JSON pointer to the value within the request body.
+    pub instance_path: String,
+    /// The schema keyword that rejected the value, if it failed validation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>,
+    pub message: String,
+}
+
+impl ValidatedJsonError {
+    /// The problem details the error responds with.
+    pub fn problem(&self) -> ProblemDetails {
+        let status = self.status_code();
+        let (detail, errors) = match self {
+            ValidatedJsonError::Payload(err) => (err.to_string(), Vec::new()),
+            ValidatedJsonError::MissingSchema(_)
+            | ValidatedJsonError::Rejected(ValidateIntoError::InvalidSchema(_)) => (
+                "The request body could not be validated".to_string(),
+                Vec::new(),
+            ),
+            ValidatedJsonError::Rejected(ValidateIntoError::Validation(report)) => (
+                "The request body failed validation".to_string(),
+                report
+                    .issues
+                    .iter()
+                    .map(|issue| ProblemError {
+                        instance_path: issue.instance_path.clone(),
+                        keyword: Some(issue.keyword.clone()),
+                        message: issue.message.clone(),
+                    })
+                    .collect(),
+            ),
+            ValidatedJsonError::Rejected(ValidateIntoError::Deserialization {
+                instance_path,
+                message,
+            }) => (
+                "The request body does not fit the expected type".to_string(),
+                vec![ProblemError {
+                    instance_path: instance_path.clone(),
+                    keyword: None,
+                    message: message.clone(),
+                }],
+            ),
+        };
+
+        ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status
+                .canonical_reason()
+                .unwrap_or("Bad Request")
+                .to_string(),
+            status: status.as_u16(),
+            detail,
+            errors,
+        }
+    }
+}
+
+impl std::fmt::Display for ValidatedJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidatedJsonError::Payload(err) => write!(f, "Invalid json request body: {err}"),
+            ValidatedJsonError::MissingSchema(type_name) => {
+                write!(f, "No json schema configured for {type_name}")
+            }
+            ValidatedJsonError::Rejected(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl ResponseError for ValidatedJsonError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ValidatedJsonError::Payload(err) => err.as_response_error().status_code(),
+            ValidatedJsonError::MissingSchema(_)
+            | ValidatedJsonError::Rejected(ValidateIntoError::InvalidSchema(_)) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            ValidatedJsonError::Rejected(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let body = serde_json::to_string(&self.problem()).unwrap_or_default();
+
+        HttpResponse::build(self.status_code())
+            .insert_header((CONTENT_TYPE, PROBLEM_JSON))
+            .body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::CONTENT_TYPE;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpResponse};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::{ValidatedJson, ValidatedJsonConfig, PROBLEM_JSON};
+    use crate::registry::SchemaRegistry;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Payment {
+        reference: String,
+        iban: String,
+        amount: u8,
+    }
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string", "minLength": 1 },
+                "iban": { "type": "string", "format": "iban", "minLength": 15 },
+                "amount": { "type": "integer", "minimum": 1 }
+            },
+            "required": ["reference", "iban", "amount"]
+        })
+    }
+
+    async fn pay(payment: ValidatedJson<Payment>) -> HttpResponse {
+        HttpResponse::Ok().body(format!(
+            "{} {} {}",
+            payment.reference, payment.iban, payment.amount
+        ))
+    }
+
+    async fn call(config: ValidatedJsonConfig, body: Value) -> (StatusCode, String, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .route("/payments", web::post().to(pay)),
+        )
+        .await;
+        let request = test::TestRequest::post()
+            .uri("/payments")
+            .set_json(body)
+            .to_request();
+
+        let response = test::call_service(&app, request).await;
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let body = test::read_body(response).await;
+        let body = serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+
+        (status, content_type, body)
+    }
+
+    #[actix_web::test]
+    async fn valid_bodies_are_extracted() {
+        let config = ValidatedJsonConfig::new()
+            .with_schema::<Payment>(&schema())
+            .unwrap();
+
+        let (status, _, body) = call(
+            config,
+            json!({ "reference": "CASE-1", "iban": "GB82WEST12345698765432", "amount": 5 }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!("CASE-1 GB82WEST12345698765432 5"));
+    }
+
+    #[actix_web::test]
+    async fn invalid_bodies_get_redacted_problem_details() {
+        let config = ValidatedJsonConfig::new()
+            .with_schema::<Payment>(&schema())
+            .unwrap();
+
+        let (status, content_type, body) = call(
+            config,
+            json!({ "reference": "", "iban": "GB82WEST", "amount": 5 }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(
+            body,
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "The request body failed validation",
+                "errors": [
+                    {
+                        "instancePath": "/iban",
+                        "keyword": "minLength",
+                        "message": "\"[REDACTED]\" is shorter than 15 characters"
+                    },
+                    {
+                        "instancePath": "/reference",
+                        "keyword": "minLength",
+                        "message": "\"\" is shorter than 1 character"
+                    }
+                ]
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn deserialization_failures_are_reported_with_their_path() {
+        let mut registry = SchemaRegistry::new();
+        registry.register("payment.json", schema()).unwrap();
+        let config = ValidatedJsonConfig::new()
+            .with_registry(registry)
+            .with_schema_uri::<Payment>("payment.json")
+            .unwrap();
+
+        let (status, _, body) = call(
+            config,
+            json!({ "reference": "CASE-1", "iban": "GB82WEST12345698765432", "amount": 500 }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["errors"][0]["instancePath"], json!("/amount"));
+        assert_eq!(
+            body["errors"][0]["message"],
+            json!("invalid value: integer `500`, expected u8")
+        );
+    }
+
+    #[actix_web::test]
+    async fn registered_schemas_resolve_relative_references_from_their_own_uri() {
+        let mut registry = SchemaRegistry::new();
+        registry
+            .register(
+                "common/iban.json",
+                json!({ "type": "string", "format": "iban", "minLength": 15 }),
+            )
+            .unwrap();
+        registry
+            .register(
+                "common/payment.json",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "reference": { "type": "string" },
+                        "iban": { "$ref": "iban.json" },
+                        "amount": { "type": "integer" }
+                    }
+                }),
+            )
+            .unwrap();
+        let config = ValidatedJsonConfig::new()
+            .with_registry(registry)
+            .with_schema_uri::<Payment>("common/payment.json")
+            .unwrap();
+
+        let (status, content_type, body) = call(
+            config,
+            json!({ "reference": "CASE-1", "iban": "GB82WEST", "amount": 5 }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(
+            body["errors"],
+            json!([{
+                "instancePath": "/iban",
+                "keyword": "minLength",
+                "message": "\"[REDACTED]\" is shorter than 15 characters"
+            }])
+        );
+    }
+
+    #[actix_web::test]
+    async fn unconfigured_types_are_a_server_error() {
+        let (status, content_type, body) =
+            call(ValidatedJsonConfig::new(), json!({ "reference": "CASE-1" })).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(body["status"], json!(500));
+        assert!(body.get("errors").is_none());
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use jsonschema::Validator;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::report::ValidationReport;
+
+/// Options controlling how `BatchValidator` works through records.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// The maximum number of issues to collect per record, or `None` to collect them all.
+    pub max_errors: Option<usize>,
+    /// The number of threads to validate each chunk of records with. 1 validates on the calling
+    /// thread.
+    pub parallelism: usize,
+    /// The number of records read ahead and validated together. Only this many records are held
+    /// in memory at once.
+    pub chunk_size: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            max_errors: None,
+            parallelism: 1,
+            chunk_size: 1000,
+        }
+    }
+}
+
+/// The outcome of validating one record.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum RecordStatus {
+    Valid,
+    Invalid(ValidationReport),
+    /// The record isn't JSON, or couldn't be read.
+    Malformed {
+        message: String,
+    },
+}
+
+/// The result of validating one record of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordResult {
+    /// The record's 1-based position in the batch, which for NDJSON is its line number.
+    pub line: usize,
+    #[serde(flatten)]
+    pub status: RecordStatus,
+}
+
+impl RecordResult {
+    pub fn is_valid(&self) -> bool {
+        self.status == RecordStatus::Valid
+    }
+}
+
+/// Validates many instances against one schema, compiling it only once.
+///
+/// Results are produced lazily and in order, a chunk of records at a time, so arbitrarily large
+/// inputs can be validated without holding them in memory.
+pub struct BatchValidator {
+    validator: Validator,
+    options: BatchOptions,
+}
+
+impl BatchValidator {
+    pub fn new(schema: &Value, options: BatchOptions) -> Result<Self> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|err| anyhow!("Invalid json schema, error: {err}"))?;
+
+        Ok(Self::with_validator(validator, options))
+    }
+
+    /// Validates with an already compiled validator, such as one from a `SchemaRegistry` or
+    /// `FormatRegistry`.
+    pub fn with_validator(validator: Validator, options: BatchOptions) -> Self {
+        BatchValidator { validator, options }
+    }
+
+    /// Validates each instance, numbering them from 1.
+    pub fn validate<'a, I>(&'a self, instances: I) -> impl Iterator<Item = RecordResult> + 'a
+    where
+        I: IntoIterator<Item = Value>,
+        I::IntoIter: 'a,
+    {
+        let records = instances
+            .into_iter()
+            .enumerate()
+            .map(|(index, instance)| (index + 1, Ok(instance)));
+
+        BatchResults::new(self, records)
+    }
+
+    /// Validates each line of newline-delimited JSON read from the reader. Blank lines are
+    /// skipped, but still counted for line numbers. A line that can't be read is reported as
+    /// malformed and ends the batch.
+    pub fn validate_ndjson<'a, R>(&'a self, reader: R) -> impl Iterator<Item = RecordResult> + 'a
+    where
+        R: BufRead + 'a,
+    {
+        let mut failed = false;
+        let records = reader
+            .lines()
+            .enumerate()
+            .map_while(move |(index, line)| {
+                if failed {
+                    return None;
+                }
+                let record = match line {
+                    Ok(line) if line.trim().is_empty() => None,
+                    Ok(line) => Some(serde_json::from_str(&line).map_err(|err| err.to_string())),
+                    Err(err) => {
+                        failed = true;
+                        Some(Err(format!("Failed to read line, error: {err}")))
+                    }
+                };
+                Some((index + 1, record))
+            })
+            .filter_map(|(line, record)| record.map(|record| (line, record)));
+
+        BatchResults::new(self, records)
+    }
+
+    fn check(&self, line: usize, record: &Result<Value, String>) -> RecordResult {
+        let status = match record {
+            Ok(instance) => {
+                let report =
+                    ValidationReport::collect(&self.validator, instance, self.options.max_errors);
+                if report.is_valid() {
+                    RecordStatus::Valid
+                } else {
+                    RecordStatus::Invalid(report)
+                }
+            }
+            Err(message) => RecordStatus::Malformed {
+                message: message.clone(),
+            },
+        };
+
+        RecordResult { line, status }
+    }
+
+    /// Validates a chunk of records, splitting it between threads if configured to.
+    fn check_chunk(&self, chunk: &[(usize, Result<Value, String>)]) -> Vec<RecordResult> {
+        let check_all = |records: &[(usize, Result<Value, String>)]| -> Vec<RecordResult> {
+            records
+                .iter()
+                .map(|(line, record)| self.check(*line, record))
+                .collect()
+        };
+        if self.options.parallelism <= 1 || chunk.len() < 2 {
+            return check_all(chunk);
+        }
+
+        let part_size = chunk.len().div_ceil(self.options.parallelism);
+        thread::scope(|scope| {
+            let parts: Vec<_> = chunk
+                .chunks(part_size)
+                .map(|part| scope.spawn(move || check_all(part)))
+                .collect();
+
+            parts
+                .into_iter()
+                .flat_map(|part| part.join().expect("Validation thread panicked"))
+                .collect()
+        })
+    }
+}
+
+/// Pulls records from the source a chunk at a time and hands back their results in order.
+struct BatchResults<'v, I> {
+    batch: &'v BatchValidator,
+    records: I,
+    results: VecDeque<RecordResult>,
+}
+
+impl<'v, I> BatchResults<'v, I>
+where
+    I: Iterator<Item = (usize, Result<Value, String>)>,
+{
+    fn new(batch: &'v BatchValidator, records: I) -> Self {
+        BatchResults {
+            batch,
+            records,
+            results: VecDeque::new(),
+        }
+    }
+}
+
+impl<I> Iterator for BatchResults<'_, I>
+where
+    I: Iterator<Item = (usize, Result<Value, String>)>,
+{
+    type Item = RecordResult;
+
+    fn next(&mut self) -> Option<RecordResult> {
+        if self.results.is_empty() {
+            let chunk: Vec<_> = self
+                .records
+                .by_ref()
+                .take(self.batch.options.chunk_size.max(1))
+                .collect();
+            self.results.extend(self.batch.check_chunk(&chunk));
+        }
+
+        self.results.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use serde_json::json;
+
+    use super::{BatchOptions, BatchValidator, RecordStatus};
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "accountId": { "type": "string" },
+                "balance": { "type": "number", "minimum": 0 }
+            },
+            "required": ["accountId"]
+        })
+    }
+
+    #[test]
+    fn validate_reports_each_record_in_order() {
+        let batch = BatchValidator::new(&schema(), BatchOptions::default()).unwrap();
+        let instances = vec![
+            json!({ "accountId": "A1", "balance": 10 }),
+            json!({ "balance": -1 }),
+            json!({ "accountId": "A3" }),
+        ];
+
+        let results: Vec<_> = batch.validate(instances).collect();
+
+        assert_eq!(
+            results.iter().map(|result| result.line).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(results[0].is_valid() && results[2].is_valid());
+        let RecordStatus::Invalid(report) = &results[1].status else {
+            panic!("expected record 2 to be invalid");
+        };
+        assert_eq!(report.issues.len(), 2);
+    }
+
+    #[test]
+    fn validate_ndjson_numbers_results_by_line() {
+        let batch = BatchValidator::new(
+            &schema(),
+            BatchOptions {
+                max_errors: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let ndjson = "{\"accountId\": \"A1\"}\n\n{\"balance\": -1}\nnot json\n";
+
+        let results: Vec<_> = batch.validate_ndjson(Cursor::new(ndjson)).collect();
+
+        assert_eq!(
+            serde_json::to_value(&results).unwrap(),
+            json!([
+                { "line": 1, "status": "valid" },
+                {
+                    "line": 3,
+                    "status": "invalid",
+                    "issues": [{
+                        "instancePath": "/balance",
+                        "schemaPath": "/properties/balance/minimum",
+                        "keyword": "minimum",
+                        "message": "-1 is less than the minimum of 0",
+                        "value": -1
+                    }],
+                    "truncated": true
+                },
+                {
+                    "line": 4,
+                    "status": "malformed",
+                    "message": "expected ident at line 1 column 2"
+                }
+            ])
+        );
+    }
+
+    #[test]
+    fn parallel_validation_matches_sequential() {
+        let ndjson: String = (0..500)
+            .map(|index| {
+                if index % 7 == 0 {
+                    format!("{{\"balance\": {index}}}\n")
+                } else {
+                    format!("{{\"accountId\": \"A{index}\", \"balance\": {index}}}\n")
+                }
+            })
+            .collect();
+        let sequential = BatchValidator::new(&schema(), BatchOptions::default()).unwrap();
+        let parallel = BatchValidator::new(
+            &schema(),
+            BatchOptions {
+                parallelism: 4,
+                chunk_size: 64,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let expected: Vec<_> = sequential
+            .validate_ndjson(Cursor::new(ndjson.clone()))
+            .collect();
+        let actual: Vec<_> = parallel.validate_ndjson(Cursor::new(ndjson)).collect();
+
+        assert_eq!(actual, expected);
+        assert_eq!(actual.len(), 500);
+        assert_eq!(
+            actual.iter().filter(|result| !result.is_valid()).count(),
+            72
+        );
+    }
+}
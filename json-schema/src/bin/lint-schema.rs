@@ -0,0 +1,57 @@
+//! Lints hand-written JSON schemas, printing every finding.
+//!
+//! Usage: `lint-schema [--deny-warnings] <schema.json>...`
+//!
+//! Exits with 1 if any schema has an error, or a warning with `--deny-warnings`, and with 2 if a
+//! schema can't be read.
+
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+use anyhow::{bail, Context, Result};
+use flexys_json_schema::lint::{lint_schema, Severity};
+use serde_json::Value;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("{err:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Lints the schemas named on the command line, returning whether they all passed.
+fn run() -> Result<bool> {
+    let mut deny_warnings = false;
+    let mut paths = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--deny-warnings" => deny_warnings = true,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        bail!("Usage: lint-schema [--deny-warnings] <schema.json>...");
+    }
+
+    let mut passed = true;
+    for path in paths {
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read schema {path}"))?;
+        let schema: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse schema {path}"))?;
+
+        for finding in lint_schema(&schema) {
+            println!("{path}: {finding}");
+            if finding.severity == Severity::Error || deny_warnings {
+                passed = false;
+            }
+        }
+    }
+
+    Ok(passed)
+}
@@ -0,0 +1,457 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use jsonschema::Draft;
+use serde_json::{Map, Value};
+
+use crate::inspector::SchemaInspector;
+use crate::registry::{DATA_KEYWORDS, SCHEMA_MAP_KEYWORDS};
+use crate::util::{escape_pointer_segment, unescape_pointer_segment};
+use crate::walk::{SchemaNode, SchemaWalker};
+
+/// How `bundle` gets rid of references to other schemas.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BundleMode {
+    /// Every referenced schema is copied into the root's `$defs`, or `definitions` before draft
+    /// 2019-09, and references are rewritten to point there.
+    #[default]
+    Definitions,
+    /// Every reference is replaced by a copy of the schema it refers to.
+    Inline,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+    pub mode: BundleMode,
+    /// Fail when a schema refers to itself, rather than keeping it in `$defs` so it can.
+    pub reject_recursion: bool,
+}
+
+/// The result of `bundle`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bundle {
+    /// The self-contained schema.
+    pub schema: Value,
+    /// The `$defs` entries for schemas that refer to themselves, directly or through others.
+    /// When inlining, these are the only references left.
+    pub recursive: Vec<String>,
+}
+
+/// Bundles the inspector's schema and every schema it references into one self-contained
+/// document, for tools that can't follow `$ref`s across documents.
+///
+/// References back to the root are left as `#`. Recursive schemas can't be inlined, so they are
+/// moved into `$defs` even when inlining, unless `reject_recursion` is set. Bundled schemas lose
+/// their own `$id` and `$schema`, and `$defs` that nothing refers to are dropped.
+pub fn bundle(inspector: &SchemaInspector, options: &BundleOptions) -> Result<Bundle> {
+    inspector.walk(|walker, root| {
+        let definitions_keyword = match root.draft {
+            Draft::Draft4 | Draft::Draft6 | Draft::Draft7 => "definitions",
+            _ => "$defs",
+        };
+        let mut bundler = Bundler {
+            walker,
+            root: root.schema,
+            definitions_keyword,
+            options,
+            names: HashMap::new(),
+            definitions: BTreeMap::new(),
+            expanding: Vec::new(),
+            recursive: HashSet::new(),
+        };
+
+        let mut schema = bundler.rewrite(&root)?;
+        let mut recursive: Vec<String> = bundler
+            .recursive
+            .iter()
+            .filter_map(|target| bundler.names.get(target).cloned())
+            .collect();
+        recursive.sort();
+
+        if let (Value::Object(object), false) = (&mut schema, bundler.definitions.is_empty()) {
+            object.insert(
+                definitions_keyword.to_string(),
+                Value::Object(bundler.definitions.into_iter().collect()),
+            );
+        }
+
+        Ok(Bundle { schema, recursive })
+    })
+}
+
+struct Bundler<'w, 'r> {
+    walker: &'w SchemaWalker<'r>,
+    root: *const Value,
+    definitions_keyword: &'static str,
+    options: &'w BundleOptions,
+    /// The `$defs` name given to each schema that has one, by its location in the registry.
+    names: HashMap<*const Value, String>,
+    definitions: BTreeMap<String, Value>,
+    /// The referenced schemas currently being rewritten, to detect recursion.
+    expanding: Vec<*const Value>,
+    recursive: HashSet<*const Value>,
+}
+
+impl<'r> Bundler<'_, 'r> {
+    fn rewrite(&mut self, node: &SchemaNode<'r>) -> Result<Value> {
+        match node.schema {
+            Value::Object(object) => {
+                let is_root = std::ptr::eq(node.schema, self.root);
+                let mut rewritten = Map::new();
+                for (key, value) in object {
+                    match key.as_str() {
+                        "$ref" | "$defs" | "definitions" => {}
+                        "$id" | "$schema" if !is_root => {}
+                        _ if DATA_KEYWORDS.contains(&key.as_str()) => {
+                            rewritten.insert(key.clone(), value.clone());
+                        }
+                        // The keys are names, so are kept whatever they are.
+                        _ if SCHEMA_MAP_KEYWORDS.contains(&key.as_str()) && value.is_object() => {
+                            let mut schemas = Map::new();
+                            for (name, schema) in value.as_object().into_iter().flatten() {
+                                let child = node.child(&[key, name], schema)?;
+                                schemas.insert(name.clone(), self.rewrite(&child)?);
+                            }
+                            rewritten.insert(key.clone(), Value::Object(schemas));
+                        }
+                        _ => {
+                            let child = node.child(&[key], value)?;
+                            rewritten.insert(key.clone(), self.rewrite(&child)?);
+                        }
+                    }
+                }
+
+                match object.get("$ref").and_then(Value::as_str) {
+                    Some(reference) => self.replace_reference(node, reference, rewritten),
+                    None => Ok(Value::Object(rewritten)),
+                }
+            }
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| self.rewrite(&node.child(&[&index.to_string()], item)?))
+                .collect::<Result<Vec<_>>>()
+                .map(Value::Array),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Replaces the reference with a reference into `$defs` or with the schema it refers to.
+    /// `siblings` are the rewritten keywords alongside the `$ref`.
+    fn replace_reference(
+        &mut self,
+        node: &SchemaNode<'r>,
+        reference: &str,
+        mut siblings: Map<String, Value>,
+    ) -> Result<Value> {
+        let target = self.walker.follow(node, "$ref", reference)?;
+        let key = target.schema as *const Value;
+
+        if key == self.root {
+            siblings.insert("$ref".to_string(), Value::String("#".to_string()));
+            return Ok(Value::Object(siblings));
+        }
+
+        if self.expanding.contains(&key) {
+            if self.options.reject_recursion {
+                bail!(
+                    "Recursive reference \"{reference}\" at {} can't be bundled",
+                    node.schema_path
+                );
+            }
+            self.recursive.insert(key);
+            self.name_for(key, reference);
+        }
+
+        if !self.names.contains_key(&key) || !self.definitions.contains_key(&self.names[&key]) {
+            if self.options.mode == BundleMode::Definitions {
+                self.name_for(key, reference);
+            }
+            if !self.expanding.contains(&key) {
+                self.expanding.push(key);
+                let expanded = self.rewrite(&target);
+                self.expanding.pop();
+                let expanded = expanded?;
+
+                match self.names.get(&key) {
+                    Some(name) => {
+                        self.definitions.insert(name.clone(), expanded);
+                    }
+                    None => return Ok(merge(expanded, siblings)),
+                }
+            }
+        }
+
+        siblings.insert(
+            "$ref".to_string(),
+            Value::String(format!(
+                "#/{}/{}",
+                self.definitions_keyword,
+                escape_pointer_segment(&self.names[&key])
+            )),
+        );
+        Ok(Value::Object(siblings))
+    }
+
+    /// The `$defs` name for the referenced schema, naming it after the end of the reference if
+    /// it doesn't have one yet.
+    fn name_for(&mut self, key: *const Value, reference: &str) -> String {
+        if let Some(name) = self.names.get(&key) {
+            return name.clone();
+        }
+
+        let (base, fragment) = reference.split_once('#').unwrap_or((reference, ""));
+        let stem = fragment
+            .rsplit('/')
+            .find(|segment| !segment.is_empty())
+            .map(unescape_pointer_segment)
+            .or_else(|| {
+                base.rsplit('/')
+                    .find(|segment| !segment.is_empty())
+                    .map(|file| file.trim_end_matches(".json").to_string())
+            })
+            .unwrap_or_else(|| "schema".to_string());
+
+        let taken: HashSet<&String> = self.names.values().collect();
+        let name = (1..)
+            .map(|count| {
+                if count == 1 {
+                    stem.clone()
+                } else {
+                    format!("{stem}{count}")
+                }
+            })
+            .find(|name| !taken.contains(name))
+            .expect("There is always an unused name");
+
+        self.names.insert(key, name.clone());
+        name
+    }
+}
+
+/// Combines an inlined schema with the keywords that sat alongside its `$ref`, merging them
+/// into one object where they don't overlap.
+fn merge(inlined: Value, siblings: Map<String, Value>) -> Value {
+    if siblings.is_empty() {
+        return inlined;
+    }
+
+    match inlined {
+        Value::Object(mut object) if siblings.keys().all(|key| !object.contains_key(key)) => {
+            object.extend(siblings);
+            Value::Object(object)
+        }
+        inlined => {
+            let mut combined = siblings;
+            combined.insert("allOf".to_string(), Value::Array(vec![inlined]));
+            Value::Object(combined)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{bundle, Bundle, BundleMode, BundleOptions};
+    use crate::inspector::SchemaInspector;
+    use crate::registry::SchemaRegistry;
+
+    fn registry() -> SchemaRegistry {
+        let mut registry = SchemaRegistry::new();
+        registry
+            .register(
+                "common/address.json",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "line1": { "type": "string" },
+                        "postcode": { "$ref": "postcode.json" }
+                    },
+                    "required": ["line1"]
+                }),
+            )
+            .unwrap();
+        registry
+            .register(
+                "common/postcode.json",
+                json!({ "type": "string", "maxLength": 8 }),
+            )
+            .unwrap();
+        registry
+    }
+
+    fn schema() -> Value {
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "home": { "$ref": "common/address.json" },
+                "work": { "$ref": "common/address.json", "description": "Work address" },
+                "amount": { "$ref": "#/$defs/money" }
+            },
+            "$defs": {
+                "money": { "type": "number", "minimum": 0 },
+                "unused": { "type": "string" }
+            }
+        })
+    }
+
+    fn bundled(schema: &Value, mode: BundleMode) -> Bundle {
+        let inspector = SchemaInspector::with_registry(schema, &registry()).unwrap();
+        let options = BundleOptions {
+            mode,
+            ..Default::default()
+        };
+
+        bundle(&inspector, &options).unwrap()
+    }
+
+    #[test]
+    fn bundle_moves_referenced_schemas_into_defs() {
+        let bundle = bundled(&schema(), BundleMode::Definitions);
+
+        assert_eq!(
+            bundle.schema,
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "home": { "$ref": "#/$defs/address" },
+                    "work": { "$ref": "#/$defs/address", "description": "Work address" },
+                    "amount": { "$ref": "#/$defs/money" }
+                },
+                "$defs": {
+                    "address": {
+                        "type": "object",
+                        "properties": {
+                            "line1": { "type": "string" },
+                            "postcode": { "$ref": "#/$defs/postcode" }
+                        },
+                        "required": ["line1"]
+                    },
+                    "money": { "type": "number", "minimum": 0 },
+                    "postcode": { "type": "string", "maxLength": 8 }
+                }
+            })
+        );
+        assert!(bundle.recursive.is_empty());
+
+        let validator = jsonschema::validator_for(&bundle.schema).unwrap();
+        assert!(
+            validator.is_valid(&json!({ "home": { "line1": "1 High St", "postcode": "AB1 2CD" } }))
+        );
+        assert!(!validator
+            .is_valid(&json!({ "home": { "line1": "1 High St", "postcode": "far too long" } })));
+    }
+
+    #[test]
+    fn bundle_can_inline_every_reference() {
+        let bundle = bundled(&schema(), BundleMode::Inline);
+        let address = json!({
+            "type": "object",
+            "properties": {
+                "line1": { "type": "string" },
+                "postcode": { "type": "string", "maxLength": 8 }
+            },
+            "required": ["line1"]
+        });
+        let mut work_address = address.clone();
+        work_address["description"] = json!("Work address");
+
+        assert_eq!(
+            bundle.schema,
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "home": address,
+                    "work": work_address,
+                    "amount": { "type": "number", "minimum": 0 }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn recursive_schemas_stay_in_defs() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "properties": {
+                "tree": { "$ref": "#/definitions/node" },
+                "parent": { "$ref": "#" }
+            },
+            "definitions": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "label": { "type": "string" },
+                        "children": { "type": "array", "items": { "$ref": "#/definitions/node" } }
+                    }
+                }
+            }
+        });
+
+        let inlined = bundled(&schema, BundleMode::Inline);
+
+        assert_eq!(inlined.recursive, vec!["node"]);
+        assert_eq!(
+            inlined.schema["properties"],
+            json!({
+                "tree": { "$ref": "#/definitions/node" },
+                "parent": { "$ref": "#" }
+            })
+        );
+        assert_eq!(
+            inlined.schema["definitions"]["node"]["properties"]["children"]["items"],
+            json!({ "$ref": "#/definitions/node" })
+        );
+        let validator = jsonschema::validator_for(&inlined.schema).unwrap();
+        assert!(!validator.is_valid(&json!({ "tree": { "children": [{ "label": 1 }] } })));
+
+        let inspector = SchemaInspector::new(&schema).unwrap();
+        let options = BundleOptions {
+            mode: BundleMode::Inline,
+            reject_recursion: true,
+        };
+        let err = bundle(&inspector, &options).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Recursive reference \"#/definitions/node\""));
+    }
+
+    #[test]
+    fn bundle_keeps_properties_named_like_keywords() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "definitions": { "type": "string" },
+                "$ref": { "type": "number" },
+                "postcode": { "$ref": "common/postcode.json" }
+            },
+            "patternProperties": {
+                "^\\$defs": { "type": "boolean" }
+            }
+        });
+
+        let bundle = bundled(&schema, BundleMode::Inline);
+
+        assert_eq!(
+            bundle.schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "definitions": { "type": "string" },
+                    "$ref": { "type": "number" },
+                    "postcode": { "type": "string", "maxLength": 8 }
+                },
+                "patternProperties": {
+                    "^\\$defs": { "type": "boolean" }
+                }
+            })
+        );
+        let validator = jsonschema::validator_for(&bundle.schema).unwrap();
+        assert!(!validator.is_valid(&json!({ "$ref": "not a number" })));
+    }
+}
@@ -0,0 +1,169 @@
+use serde_json::{Number, Value};
+use sha2::{Digest, Sha256};
+
+/// Serializes the value with the RFC 8785 JSON Canonicalization Scheme, so that equal values
+/// always produce the same bytes.
+///
+/// There's no whitespace, object keys are sorted by their UTF-16 code units, strings only escape
+/// what JSON requires, and numbers are written as ECMAScript does. Like JavaScript, every number
+/// is treated as a double, so integers beyond 2^53 lose precision.
+///
+/// # Arguments
+///
+/// * `value`: The JSON value to serialize.
+///
+pub fn canonicalize(value: &Value) -> String {
+    let mut canonical = String::new();
+    write_value(value, &mut canonical);
+
+    canonical
+}
+
+/// The lowercase hex SHA-256 of the value's canonical serialization, for idempotency keys and
+/// change detection. Values that differ only in key order or number formatting, such as `1.0`
+/// and `1`, hash the same.
+///
+/// # Arguments
+///
+/// * `value`: The JSON value to hash.
+///
+pub fn content_hash(value: &Value) -> String {
+    let digest = Sha256::digest(canonicalize(value).as_bytes());
+
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(boolean) => out.push_str(if *boolean { "true" } else { "false" }),
+        Value::Number(number) => write_number(number, out),
+        Value::String(string) => write_string(string, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+
+            out.push('{');
+            for (index, (key, item)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_number(number: &Number, out: &mut String) {
+    // serde_json numbers are finite and always convert to a double, large integers approximately.
+    let double = number.as_f64().unwrap_or_default();
+    if double == 0.0 {
+        // ECMAScript writes negative zero as 0.
+        out.push('0');
+    } else {
+        out.push_str(ryu_js::Buffer::new().format_finite(double));
+    }
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{canonicalize, content_hash};
+
+    #[test]
+    fn canonicalize_formats_numbers_like_ecmascript() {
+        let value = json!([
+            333333333.333_333_3_f64,
+            1e30,
+            4.50,
+            2e-3,
+            0.000001,
+            1e-7,
+            -0.0,
+            100,
+            1.0,
+            -12
+        ]);
+
+        assert_eq!(
+            canonicalize(&value),
+            "[333333333.3333333,1e+30,4.5,0.002,0.000001,1e-7,0,100,1,-12]"
+        );
+    }
+
+    #[test]
+    fn canonicalize_escapes_only_what_json_requires() {
+        let value = json!("\u{20ac}$\u{f}\nA'B\"\\\\\"/");
+
+        assert_eq!(canonicalize(&value), "\"€$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\"");
+    }
+
+    #[test]
+    fn canonicalize_sorts_keys_by_utf16_code_units() {
+        let value = json!({
+            "\u{20ac}": "Euro Sign",
+            "\r": "Carriage Return",
+            "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\u{1f600}": "Emoji: Grinning Face",
+            "\u{80}": "Control",
+            "\u{f6}": "Latin Small Letter O With Diaeresis",
+            "nested": { "b": [true, null], "a": { "z": 1, "y": 2 } }
+        });
+
+        assert_eq!(
+            canonicalize(&value),
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"nested\":{\"a\":{\"y\":2,\"z\":1},\
+             \"b\":[true,null]},\"\u{80}\":\"Control\",\"ö\":\"Latin Small Letter O With \
+             Diaeresis\",\"€\":\"Euro Sign\",\"\u{1f600}\":\"Emoji: Grinning Face\",\
+             \"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+        );
+    }
+
+    #[test]
+    fn content_hash_ignores_key_order_and_number_formatting() {
+        let a: serde_json::Value =
+            serde_json::from_str(r#"{"amount": 10.0, "reference": "A1"}"#).unwrap();
+        let b: serde_json::Value =
+            serde_json::from_str(r#"{ "reference": "A1", "amount": 1e1 }"#).unwrap();
+
+        assert_eq!(content_hash(&a), content_hash(&b));
+        assert_eq!(
+            content_hash(&json!({})),
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+        assert_ne!(content_hash(&a), content_hash(&json!({ "amount": 10.5 })));
+    }
+}
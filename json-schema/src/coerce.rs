@@ -0,0 +1,307 @@
+use anyhow::Result;
+use serde_json::{Number, Value};
+
+use crate::inspector::{field_types, SchemaInspector};
+use crate::walk::BranchMode;
+
+/// How forgiving `coerce_types` is about the strings it converts.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum CoercionMode {
+    /// Only the JSON spelling of a value is converted: `42`, `-1.5e3`, `true`, `false` and `null`.
+    #[default]
+    Strict,
+    /// Surrounding whitespace is ignored, booleans may also be written `yes`/`no`, `y`/`n`,
+    /// `on`/`off` or `1`/`0` in any case, integers may have a zero fraction such as `42.0`, and
+    /// empty strings become null where null is allowed.
+    Lenient,
+}
+
+/// Options controlling how `coerce_types` converts strings.
+#[derive(Debug, Clone)]
+pub struct CoercionOptions {
+    pub mode: CoercionMode,
+    /// Separates the items of a string converted to an array.
+    pub delimiter: String,
+}
+
+impl Default for CoercionOptions {
+    fn default() -> Self {
+        CoercionOptions {
+            mode: CoercionMode::default(),
+            delimiter: ",".to_string(),
+        }
+    }
+}
+
+/// A string in the instance that was converted to another type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeCoercion {
+    /// JSON pointer to the value within the instance.
+    pub path: String,
+    /// The type the string was converted to.
+    pub target_type: String,
+    pub original: Value,
+    pub coerced: Value,
+}
+
+/// The result of `coerce_types`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoercedInstance {
+    pub instance: Value,
+    /// Every conversion made, in the order they were made. Items of a string converted to an
+    /// array are listed after the array.
+    pub coercions: Vec<TypeCoercion>,
+}
+
+/// Converts strings in the instance to the types the schema expects, so that inputs from query
+/// strings, form data or CSV files can be validated.
+///
+/// Strings are only converted where the schema doesn't allow a string. Where it allows several
+/// types they are tried in the order null, boolean, integer, number and then array; a string
+/// converted to an array is split on `options.delimiter` and its items are converted in turn.
+/// Strings that can't be converted are left for validation to report.
+pub fn coerce_types(
+    inspector: &SchemaInspector,
+    instance: &Value,
+    options: &CoercionOptions,
+) -> Result<CoercedInstance> {
+    let mut instance = instance.clone();
+    let mut coercions = Vec::new();
+
+    inspector.visit(
+        &mut instance,
+        BranchMode::All,
+        &mut |_, nodes, value, path| {
+            let Value::String(text) = value else {
+                return Ok(());
+            };
+            let (unconditional, conditional): (Vec<_>, Vec<_>) =
+                nodes.iter().partition(|node| !node.conditional);
+            let types = field_types(&unconditional, &conditional);
+            if types.is_empty() || types.iter().any(|field_type| field_type == "string") {
+                return Ok(());
+            }
+
+            let coerced = ["null", "boolean", "integer", "number", "array"]
+                .into_iter()
+                .filter(|target_type| types.iter().any(|field_type| field_type == target_type))
+                .find_map(|target_type| {
+                    coerce_string(text, target_type, options).map(|coerced| (target_type, coerced))
+                });
+
+            if let Some((target_type, coerced)) = coerced {
+                coercions.push(TypeCoercion {
+                    path: path.to_string(),
+                    target_type: target_type.to_string(),
+                    original: value.clone(),
+                    coerced: coerced.clone(),
+                });
+                *value = coerced;
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(CoercedInstance {
+        instance,
+        coercions,
+    })
+}
+
+/// Converts a string to a value of the JSON schema type, if it can be read as one.
+pub fn coerce_string(text: &str, target_type: &str, options: &CoercionOptions) -> Option<Value> {
+    let lenient = options.mode == CoercionMode::Lenient;
+    let text = if lenient { text.trim() } else { text };
+
+    match target_type {
+        "null" => {
+            let is_null = text == "null"
+                || (lenient && (text.is_empty() || text.eq_ignore_ascii_case("null")));
+            is_null.then_some(Value::Null)
+        }
+        "boolean" => {
+            let boolean = if lenient {
+                match text.to_ascii_lowercase().as_str() {
+                    "true" | "yes" | "y" | "on" | "1" => Some(true),
+                    "false" | "no" | "n" | "off" | "0" => Some(false),
+                    _ => None,
+                }
+            } else {
+                text.parse::<bool>().ok()
+            };
+            boolean.map(Value::Bool)
+        }
+        "integer" => {
+            let number = parse_number(text, lenient)?;
+            if number.is_i64() || number.is_u64() {
+                return Some(Value::Number(number));
+            }
+            let float = number.as_f64().filter(|_| lenient)?;
+            (float.fract() == 0.0 && float.abs() < i64::MAX as f64)
+                .then(|| Value::Number((float as i64).into()))
+        }
+        "number" => parse_number(text, lenient).map(Value::Number),
+        "array" if text.is_empty() => Some(Value::Array(Vec::new())),
+        "array" => Some(Value::Array(
+            text.split(options.delimiter.as_str())
+                .map(|item| Value::String(if lenient { item.trim() } else { item }.to_string()))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+fn parse_number(text: &str, lenient: bool) -> Option<Number> {
+    if let Ok(number) = text.parse::<Number>() {
+        return Some(number);
+    }
+    if !lenient {
+        return None;
+    }
+
+    let text = text.strip_prefix('+').unwrap_or(text);
+    text.parse::<Number>().ok().or_else(|| {
+        text.parse::<f64>()
+            .ok()
+            .filter(|float| float.is_finite())
+            .and_then(Number::from_f64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{coerce_string, coerce_types, CoercionMode, CoercionOptions};
+    use crate::inspector::SchemaInspector;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "amount": { "type": "number" },
+                "instalments": { "type": "integer" },
+                "active": { "type": "boolean" },
+                "closedOn": { "type": ["string", "null"] },
+                "agentId": { "type": ["integer", "null"] },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "days": { "type": "array", "items": { "$ref": "#/$defs/day" } },
+                "reference": { "type": "string" }
+            },
+            "$defs": {
+                "day": { "type": "integer", "minimum": 1, "maximum": 31 }
+            }
+        })
+    }
+
+    #[test]
+    fn coerce_types_converts_strings_to_the_schema_types() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({
+            "amount": "-12.5",
+            "instalments": "6",
+            "active": "true",
+            "closedOn": "null",
+            "agentId": "null",
+            "tags": "a,b",
+            "days": "1,15",
+            "reference": "42"
+        });
+
+        let coerced = coerce_types(&inspector, &instance, &CoercionOptions::default()).unwrap();
+
+        assert_eq!(
+            coerced.instance,
+            json!({
+                "amount": -12.5,
+                "instalments": 6,
+                "active": true,
+                "closedOn": "null",
+                "agentId": null,
+                "tags": ["a", "b"],
+                "days": [1, 15],
+                "reference": "42"
+            })
+        );
+        let paths: Vec<(&str, &str)> = coerced
+            .coercions
+            .iter()
+            .map(|coercion| (coercion.path.as_str(), coercion.target_type.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("/active", "boolean"),
+                ("/agentId", "null"),
+                ("/amount", "number"),
+                ("/days", "array"),
+                ("/days/0", "integer"),
+                ("/days/1", "integer"),
+                ("/instalments", "integer"),
+                ("/tags", "array"),
+            ]
+        );
+        assert_eq!(coerced.coercions[0].original, json!("true"));
+    }
+
+    #[test]
+    fn coerce_types_leaves_strings_it_cannot_convert() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({ "amount": "twelve", "instalments": "6.5", "active": "yes" });
+
+        let coerced = coerce_types(&inspector, &instance, &CoercionOptions::default()).unwrap();
+
+        assert_eq!(coerced.instance, instance);
+        assert!(coerced.coercions.is_empty());
+    }
+
+    #[test]
+    fn coerce_types_lenient_mode_accepts_looser_spellings() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({
+            "amount": " +12 ",
+            "instalments": "6.0",
+            "active": "Yes",
+            "agentId": "",
+            "days": "1; 15"
+        });
+        let options = CoercionOptions {
+            mode: CoercionMode::Lenient,
+            delimiter: ";".to_string(),
+        };
+
+        let coerced = coerce_types(&inspector, &instance, &options).unwrap();
+
+        assert_eq!(
+            coerced.instance,
+            json!({
+                "amount": 12,
+                "instalments": 6,
+                "active": true,
+                "agentId": null,
+                "days": [1, 15]
+            })
+        );
+    }
+
+    #[test]
+    fn coerce_string_is_strict_by_default() {
+        let strict = CoercionOptions::default();
+        let lenient = CoercionOptions {
+            mode: CoercionMode::Lenient,
+            ..Default::default()
+        };
+
+        assert_eq!(coerce_string(" 1", "integer", &strict), None);
+        assert_eq!(coerce_string(" 1", "integer", &lenient), Some(json!(1)));
+        assert_eq!(coerce_string("TRUE", "boolean", &strict), None);
+        assert_eq!(
+            coerce_string("off", "boolean", &lenient),
+            Some(json!(false))
+        );
+        assert_eq!(coerce_string("", "null", &strict), None);
+        assert_eq!(coerce_string("", "array", &strict), Some(json!([])));
+        assert_eq!(coerce_string("1e400", "number", &lenient), None);
+    }
+}
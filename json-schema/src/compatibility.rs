@@ -0,0 +1,695 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::inspector::{enum_values, field_types, SchemaInspector};
+use crate::util::escape_pointer_segment;
+use crate::walk::{BranchMode, SchemaNode, SchemaWalker};
+
+/// Keywords setting a lower bound, which tighten as they grow.
+const LOWER_BOUNDS: [&str; 5] = [
+    "minimum",
+    "exclusiveMinimum",
+    "minLength",
+    "minItems",
+    "minProperties",
+];
+
+/// Keywords setting an upper bound, which tighten as they shrink.
+const UPPER_BOUNDS: [&str; 5] = [
+    "maximum",
+    "exclusiveMaximum",
+    "maxLength",
+    "maxItems",
+    "maxProperties",
+];
+
+/// Keywords whose values can't be ordered, so any change to them is incompatible both ways.
+const EXACT_CONSTRAINTS: [&str; 4] = ["pattern", "format", "multipleOf", "uniqueItems"];
+
+/// How a new version of a schema relates to the old one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Compatibility {
+    /// Instances valid against either version are valid against the other.
+    Full,
+    /// Instances valid against the old schema are valid against the new one, so consumers can
+    /// move to the new schema before producers do.
+    Backward,
+    /// Instances valid against the new schema are valid against the old one, so producers can
+    /// move to the new schema before consumers do.
+    Forward,
+    /// Neither version accepts everything the other does.
+    Breaking,
+}
+
+/// The kind of change found by `check_compatibility`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IncompatibilityKind {
+    PropertyRemoved,
+    /// A property was added to an object that didn't allow additional properties.
+    PropertyAdded,
+    RequiredAdded,
+    RequiredRemoved,
+    TypeNarrowed,
+    TypeWidened,
+    EnumNarrowed,
+    EnumWidened,
+    AdditionalPropertiesRestricted,
+    AdditionalPropertiesRelaxed,
+    ConstraintTightened,
+    ConstraintLoosened,
+    ConstraintChanged,
+}
+
+/// A change between two versions of a schema that some instances won't survive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Incompatibility {
+    /// JSON pointer to the location in instances, with `*` standing for any array item.
+    pub path: String,
+    pub kind: IncompatibilityKind,
+    pub message: String,
+    /// Set when instances valid against the old schema may be invalid against the new one.
+    pub breaks_backward: bool,
+    /// Set when instances valid against the new schema may be invalid against the old one.
+    pub breaks_forward: bool,
+}
+
+/// The result of `check_compatibility`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatibilityReport {
+    pub compatibility: Compatibility,
+    pub incompatibilities: Vec<Incompatibility>,
+}
+
+/// Compares two versions of a schema, classifying the change and listing every incompatibility:
+/// removed properties, newly required fields, narrowed types, tightened enums and constraints.
+///
+/// The comparison is structural. Properties are compared wherever both versions declare them,
+/// following `$ref` and `allOf`; `anyOf`, `oneOf` and `if` branches are combined, so a field is
+/// taken to allow whatever any branch allows. Adding an optional property to an object that
+/// allows additional properties isn't treated as a change.
+pub fn check_compatibility(old: &Value, new: &Value) -> Result<CompatibilityReport> {
+    check_inspector_compatibility(&SchemaInspector::new(old)?, &SchemaInspector::new(new)?)
+}
+
+/// Compares two versions of a schema, see `check_compatibility`. The inspectors allow the
+/// schemas to reference others in a `SchemaRegistry`.
+pub fn check_inspector_compatibility(
+    old: &SchemaInspector,
+    new: &SchemaInspector,
+) -> Result<CompatibilityReport> {
+    let incompatibilities = old.walk(|old_walker, old_root| {
+        new.walk(|new_walker, new_root| {
+            let mut comparison = Comparison {
+                old_walker,
+                new_walker,
+                queue: VecDeque::from([(vec![old_root], vec![new_root], String::new())]),
+                visited: HashSet::new(),
+                incompatibilities: Vec::new(),
+            };
+            while let Some((old, new, path)) = comparison.queue.pop_front() {
+                comparison.compare(&old, &new, &path)?;
+            }
+            Ok(comparison.incompatibilities)
+        })
+    })?;
+
+    let backward = !incompatibilities.iter().any(|found| found.breaks_backward);
+    let forward = !incompatibilities.iter().any(|found| found.breaks_forward);
+    let compatibility = match (backward, forward) {
+        (true, true) => Compatibility::Full,
+        (true, false) => Compatibility::Backward,
+        (false, true) => Compatibility::Forward,
+        (false, false) => Compatibility::Breaking,
+    };
+
+    Ok(CompatibilityReport {
+        compatibility,
+        incompatibilities,
+    })
+}
+
+/// Which ways a change breaks compatibility.
+#[derive(Clone, Copy)]
+enum Breaks {
+    Backward,
+    Forward,
+    Both,
+}
+
+/// What a location in a schema allows, gathered from the expanded nodes that apply to it.
+struct Summary {
+    /// Empty when any type is allowed.
+    types: Vec<String>,
+    enum_values: Option<Vec<Value>>,
+    required: BTreeSet<String>,
+    properties: BTreeSet<String>,
+    closed: bool,
+    tuple_length: usize,
+    constraints: Vec<(&'static str, Option<Value>)>,
+}
+
+impl Summary {
+    fn of(nodes: &[SchemaNode]) -> Self {
+        let (unconditional, conditional): (Vec<_>, Vec<_>) =
+            nodes.iter().partition(|node| !node.conditional);
+
+        let required = unconditional
+            .iter()
+            .filter_map(|node| node.get("required").and_then(Value::as_array))
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect();
+        let properties = nodes
+            .iter()
+            .filter_map(|node| node.get("properties").and_then(Value::as_object))
+            .flat_map(|properties| properties.keys().cloned())
+            .collect();
+        let closed = unconditional.iter().any(|node| {
+            node.get("additionalProperties") == Some(&Value::Bool(false))
+                && node.get("patternProperties").is_none()
+        });
+        let tuple_length = nodes
+            .iter()
+            .filter_map(|node| match (node.get("prefixItems"), node.get("items")) {
+                (Some(Value::Array(tuple)), _) | (None, Some(Value::Array(tuple))) => {
+                    Some(tuple.len())
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let bound = |keyword: &'static str, tighter: fn(f64, f64) -> bool| {
+            let value = unconditional
+                .iter()
+                .filter_map(|node| node.get(keyword).filter(|value| value.is_number()))
+                .reduce(|bound, value| {
+                    let (a, b) = (value.as_f64(), bound.as_f64());
+                    if tighter(a.unwrap_or_default(), b.unwrap_or_default()) {
+                        value
+                    } else {
+                        bound
+                    }
+                })
+                .cloned();
+            (keyword, value)
+        };
+        let mut constraints: Vec<_> = LOWER_BOUNDS
+            .into_iter()
+            .map(|keyword| bound(keyword, |a, b| a > b))
+            .chain(
+                UPPER_BOUNDS
+                    .into_iter()
+                    .map(|keyword| bound(keyword, |a, b| a < b)),
+            )
+            .collect();
+        constraints.extend(EXACT_CONSTRAINTS.into_iter().map(|keyword| {
+            let value = unconditional
+                .iter()
+                .find_map(|node| node.get(keyword))
+                .filter(|value| **value != Value::Bool(false))
+                .cloned();
+            (keyword, value)
+        }));
+
+        Summary {
+            types: field_types(&unconditional, &conditional),
+            enum_values: enum_values(&unconditional, &conditional),
+            required,
+            properties,
+            closed,
+            tuple_length,
+            constraints,
+        }
+    }
+
+    fn allows_type(&self, field_type: &str) -> bool {
+        self.types.is_empty()
+            || self.types.iter().any(|allowed| {
+                allowed == field_type || (allowed == "number" && field_type == "integer")
+            })
+    }
+}
+
+/// Locations to compare: the old and new schema nodes that apply there, and its path.
+type Locations<'a, 'b> = VecDeque<(Vec<SchemaNode<'a>>, Vec<SchemaNode<'b>>, String)>;
+
+/// Compares locations breadth first, so a change reached again through a recursive schema is
+/// reported at its shallowest path.
+struct Comparison<'s, 'a, 'b> {
+    old_walker: &'s SchemaWalker<'a>,
+    new_walker: &'s SchemaWalker<'b>,
+    queue: Locations<'a, 'b>,
+    visited: HashSet<(Vec<*const Value>, Vec<*const Value>)>,
+    incompatibilities: Vec<Incompatibility>,
+}
+
+impl<'a, 'b> Comparison<'_, 'a, 'b> {
+    fn compare(
+        &mut self,
+        old: &[SchemaNode<'a>],
+        new: &[SchemaNode<'b>],
+        path: &str,
+    ) -> Result<()> {
+        let old = self.old_walker.expand_all(old, None, BranchMode::All)?;
+        let new = self.new_walker.expand_all(new, None, BranchMode::All)?;
+
+        // Recursive schemas revisit the same pair of locations, which have already been compared.
+        let key = (
+            old.iter().map(|node| node.schema as *const Value).collect(),
+            new.iter().map(|node| node.schema as *const Value).collect(),
+        );
+        if !self.visited.insert(key) {
+            return Ok(());
+        }
+
+        let old_summary = Summary::of(&old);
+        let new_summary = Summary::of(&new);
+        self.compare_types(&old_summary, &new_summary, path);
+        self.compare_enums(&old_summary, &new_summary, path);
+        self.compare_constraints(&old_summary, &new_summary, path);
+        self.compare_objects(&old_summary, &new_summary, path);
+
+        for property in old_summary.properties.union(&new_summary.properties) {
+            if old_summary.properties.contains(property)
+                && new_summary.properties.contains(property)
+            {
+                let old_children = self.old_walker.property_nodes(&old, property)?;
+                let new_children = self.new_walker.property_nodes(&new, property)?;
+                let path = format!("{path}/{}", escape_pointer_segment(property));
+                self.queue.push_back((old_children, new_children, path));
+            }
+        }
+
+        let tuple_length = old_summary.tuple_length.max(new_summary.tuple_length);
+        let items = (0..tuple_length)
+            .map(|index| (index, index.to_string()))
+            .chain([(usize::MAX, "*".to_string())]);
+        for (index, segment) in items {
+            let old_children = self.old_walker.item_nodes(&old, index)?;
+            let new_children = self.new_walker.item_nodes(&new, index)?;
+            if !old_children.is_empty() || !new_children.is_empty() {
+                let path = format!("{path}/{segment}");
+                self.queue.push_back((old_children, new_children, path));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compare_types(&mut self, old: &Summary, new: &Summary, path: &str) {
+        if old.types.is_empty() && !new.types.is_empty() {
+            let message = format!("Type is now restricted to {}", new.types.join(", "));
+            self.record(
+                path,
+                IncompatibilityKind::TypeNarrowed,
+                Breaks::Backward,
+                message,
+            );
+        } else {
+            let removed = old
+                .types
+                .iter()
+                .filter(|old_type| !new.allows_type(old_type));
+            let removed: Vec<&str> = removed.map(String::as_str).collect();
+            if !removed.is_empty() {
+                let message = format!("Type no longer allows {}", removed.join(", "));
+                self.record(
+                    path,
+                    IncompatibilityKind::TypeNarrowed,
+                    Breaks::Backward,
+                    message,
+                );
+            }
+        }
+
+        if new.types.is_empty() && !old.types.is_empty() {
+            let message = "Type is no longer restricted".to_string();
+            self.record(
+                path,
+                IncompatibilityKind::TypeWidened,
+                Breaks::Forward,
+                message,
+            );
+        } else {
+            let added = new
+                .types
+                .iter()
+                .filter(|new_type| !old.allows_type(new_type));
+            let added: Vec<&str> = added.map(String::as_str).collect();
+            if !added.is_empty() {
+                let message = format!("Type now also allows {}", added.join(", "));
+                self.record(
+                    path,
+                    IncompatibilityKind::TypeWidened,
+                    Breaks::Forward,
+                    message,
+                );
+            }
+        }
+    }
+
+    fn compare_enums(&mut self, old: &Summary, new: &Summary, path: &str) {
+        let list = |values: Vec<&Value>| {
+            values
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match (&old.enum_values, &new.enum_values) {
+            (None, None) => {}
+            (None, Some(new_values)) => {
+                let message = format!(
+                    "Values are now restricted to {}",
+                    list(new_values.iter().collect())
+                );
+                self.record(
+                    path,
+                    IncompatibilityKind::EnumNarrowed,
+                    Breaks::Backward,
+                    message,
+                );
+            }
+            (Some(_), None) => {
+                let message = "Values are no longer restricted".to_string();
+                self.record(
+                    path,
+                    IncompatibilityKind::EnumWidened,
+                    Breaks::Forward,
+                    message,
+                );
+            }
+            (Some(old_values), Some(new_values)) => {
+                let removed: Vec<&Value> = old_values
+                    .iter()
+                    .filter(|value| !new_values.contains(value))
+                    .collect();
+                let added: Vec<&Value> = new_values
+                    .iter()
+                    .filter(|value| !old_values.contains(value))
+                    .collect();
+                if !removed.is_empty() {
+                    let message = format!("Values no longer allow {}", list(removed));
+                    self.record(
+                        path,
+                        IncompatibilityKind::EnumNarrowed,
+                        Breaks::Backward,
+                        message,
+                    );
+                }
+                if !added.is_empty() {
+                    let message = format!("Values now also allow {}", list(added));
+                    self.record(
+                        path,
+                        IncompatibilityKind::EnumWidened,
+                        Breaks::Forward,
+                        message,
+                    );
+                }
+            }
+        }
+    }
+
+    fn compare_constraints(&mut self, old: &Summary, new: &Summary, path: &str) {
+        for ((keyword, old_value), (_, new_value)) in old.constraints.iter().zip(&new.constraints) {
+            let (kind, breaks, message) = match (old_value, new_value) {
+                (None, None) => continue,
+                (Some(old_value), Some(new_value)) if old_value == new_value => continue,
+                (None, Some(new_value)) => (
+                    IncompatibilityKind::ConstraintTightened,
+                    Breaks::Backward,
+                    format!("{keyword} {new_value} was added"),
+                ),
+                (Some(old_value), None) => (
+                    IncompatibilityKind::ConstraintLoosened,
+                    Breaks::Forward,
+                    format!("{keyword} {old_value} was removed"),
+                ),
+                (Some(old_value), Some(new_value)) => {
+                    let (old_bound, new_bound) = (old_value.as_f64(), new_value.as_f64());
+                    let tightened = match (old_bound, new_bound) {
+                        (Some(old_bound), Some(new_bound)) if LOWER_BOUNDS.contains(keyword) => {
+                            Some(new_bound > old_bound)
+                        }
+                        (Some(old_bound), Some(new_bound)) if UPPER_BOUNDS.contains(keyword) => {
+                            Some(new_bound < old_bound)
+                        }
+                        _ => None,
+                    };
+                    match tightened {
+                        Some(true) => (
+                            IncompatibilityKind::ConstraintTightened,
+                            Breaks::Backward,
+                            format!("{keyword} tightened from {old_value} to {new_value}"),
+                        ),
+                        Some(false) => (
+                            IncompatibilityKind::ConstraintLoosened,
+                            Breaks::Forward,
+                            format!("{keyword} loosened from {old_value} to {new_value}"),
+                        ),
+                        None => (
+                            IncompatibilityKind::ConstraintChanged,
+                            Breaks::Both,
+                            format!("{keyword} changed from {old_value} to {new_value}"),
+                        ),
+                    }
+                }
+            };
+            self.record(path, kind, breaks, message);
+        }
+    }
+
+    fn compare_objects(&mut self, old: &Summary, new: &Summary, path: &str) {
+        for property in old.properties.difference(&new.properties) {
+            // Without the property's schema, new instances may carry any value for it or, if
+            // additional properties are no longer allowed, old instances that have it are rejected.
+            let breaks = if new.closed {
+                Breaks::Both
+            } else {
+                Breaks::Forward
+            };
+            let message = format!("Property \"{property}\" was removed");
+            self.record(path, IncompatibilityKind::PropertyRemoved, breaks, message);
+        }
+        if old.closed {
+            for property in new.properties.difference(&old.properties) {
+                let message = format!(
+                    "Property \"{property}\" was added where additional properties weren't allowed"
+                );
+                self.record(
+                    path,
+                    IncompatibilityKind::PropertyAdded,
+                    Breaks::Forward,
+                    message,
+                );
+            }
+        }
+
+        for property in new.required.difference(&old.required) {
+            let message = format!("Property \"{property}\" is now required");
+            self.record(
+                path,
+                IncompatibilityKind::RequiredAdded,
+                Breaks::Backward,
+                message,
+            );
+        }
+        for property in old.required.difference(&new.required) {
+            if new.properties.contains(property) {
+                let message = format!("Property \"{property}\" is no longer required");
+                self.record(
+                    path,
+                    IncompatibilityKind::RequiredRemoved,
+                    Breaks::Forward,
+                    message,
+                );
+            }
+        }
+
+        match (old.closed, new.closed) {
+            (false, true) => self.record(
+                path,
+                IncompatibilityKind::AdditionalPropertiesRestricted,
+                Breaks::Backward,
+                "Additional properties are no longer allowed".to_string(),
+            ),
+            (true, false) => self.record(
+                path,
+                IncompatibilityKind::AdditionalPropertiesRelaxed,
+                Breaks::Forward,
+                "Additional properties are now allowed".to_string(),
+            ),
+            _ => {}
+        }
+    }
+
+    fn record(&mut self, path: &str, kind: IncompatibilityKind, breaks: Breaks, message: String) {
+        self.incompatibilities.push(Incompatibility {
+            path: path.to_string(),
+            kind,
+            message,
+            breaks_backward: matches!(breaks, Breaks::Backward | Breaks::Both),
+            breaks_forward: matches!(breaks, Breaks::Forward | Breaks::Both),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{check_compatibility, Compatibility, IncompatibilityKind};
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string", "maxLength": 20 },
+                "status": { "enum": ["OPEN", "CLOSED"] },
+                "balance": { "type": "number", "minimum": 0 },
+                "payments": { "type": "array", "items": { "$ref": "#/$defs/payment" } }
+            },
+            "required": ["reference"],
+            "$defs": {
+                "payment": {
+                    "type": "object",
+                    "properties": {
+                        "amount": { "type": "number" },
+                        "method": { "type": "string" }
+                    }
+                }
+            }
+        })
+    }
+
+    fn kinds(report: &super::CompatibilityReport) -> Vec<(&str, IncompatibilityKind)> {
+        report
+            .incompatibilities
+            .iter()
+            .map(|found| (found.path.as_str(), found.kind))
+            .collect()
+    }
+
+    #[test]
+    fn identical_schemas_are_fully_compatible() {
+        let report = check_compatibility(&schema(), &schema()).unwrap();
+
+        assert_eq!(report.compatibility, Compatibility::Full);
+        assert!(report.incompatibilities.is_empty());
+    }
+
+    #[test]
+    fn adding_an_optional_property_is_fully_compatible() {
+        let mut new = schema();
+        new["properties"]["notes"] = json!({ "type": "string" });
+
+        let report = check_compatibility(&schema(), &new).unwrap();
+
+        assert_eq!(report.compatibility, Compatibility::Full);
+    }
+
+    #[test]
+    fn loosening_changes_are_backward_compatible() {
+        let mut new = schema();
+        new["properties"]["status"]["enum"] = json!(["OPEN", "CLOSED", "SETTLED"]);
+        new["properties"]["reference"]["maxLength"] = json!(40);
+        new["required"] = json!([]);
+
+        let report = check_compatibility(&schema(), &new).unwrap();
+
+        assert_eq!(report.compatibility, Compatibility::Backward);
+        assert_eq!(
+            kinds(&report),
+            vec![
+                ("", IncompatibilityKind::RequiredRemoved),
+                ("/reference", IncompatibilityKind::ConstraintLoosened),
+                ("/status", IncompatibilityKind::EnumWidened),
+            ]
+        );
+        assert_eq!(
+            report.incompatibilities[1].message,
+            "maxLength loosened from 20 to 40"
+        );
+    }
+
+    #[test]
+    fn tightening_changes_are_forward_compatible() {
+        let mut new = schema();
+        new["required"] = json!(["reference", "status"]);
+        new["$defs"]["payment"]["properties"]["amount"]["type"] = json!("integer");
+
+        let report = check_compatibility(&schema(), &new).unwrap();
+
+        assert_eq!(report.compatibility, Compatibility::Forward);
+        assert_eq!(
+            kinds(&report),
+            vec![
+                ("", IncompatibilityKind::RequiredAdded),
+                ("/payments/*/amount", IncompatibilityKind::TypeNarrowed),
+            ]
+        );
+        assert_eq!(
+            report.incompatibilities[1].message,
+            "Type no longer allows number"
+        );
+    }
+
+    #[test]
+    fn removing_properties_and_narrowing_enums_is_breaking() {
+        let mut new = schema();
+        new["properties"].as_object_mut().unwrap().remove("balance");
+        new["additionalProperties"] = json!(false);
+        new["properties"]["status"]["enum"] = json!(["OPEN"]);
+
+        let report = check_compatibility(&schema(), &new).unwrap();
+
+        assert_eq!(report.compatibility, Compatibility::Breaking);
+        assert_eq!(
+            kinds(&report),
+            vec![
+                ("", IncompatibilityKind::PropertyRemoved),
+                ("", IncompatibilityKind::AdditionalPropertiesRestricted),
+                ("/status", IncompatibilityKind::EnumNarrowed),
+            ]
+        );
+        assert_eq!(
+            report.incompatibilities[2].message,
+            "Values no longer allow \"CLOSED\""
+        );
+    }
+
+    #[test]
+    fn recursive_schemas_are_compared_once() {
+        let old = json!({
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "children": { "type": "array", "items": { "$ref": "#/$defs/node" } }
+                    }
+                }
+            },
+            "$ref": "#/$defs/node"
+        });
+        let mut new = old.clone();
+        new["$defs"]["node"]["properties"]["name"]["minLength"] = json!(1);
+
+        let report = check_compatibility(&old, &new).unwrap();
+
+        assert_eq!(
+            kinds(&report),
+            vec![("/name", IncompatibilityKind::ConstraintTightened)]
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+
+use anyhow::Result;
+use chrono::{
+    DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone, Utc,
+};
+use serde_json::Value;
+
+use crate::inspector::SchemaInspector;
+use crate::walk::BranchMode;
+
+/// The day and month order used to read dates written with slashes, such as `02/03/2025`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DateLocale {
+    /// `dd/mm/yyyy`
+    #[default]
+    Uk,
+    /// `mm/dd/yyyy`
+    Us,
+    /// Only ISO-8601 dates are accepted.
+    Iso,
+}
+
+/// Options controlling which inputs `coerce_dates` accepts.
+#[derive(Debug, Clone, Default)]
+pub struct DateCoercionOptions {
+    pub locale: DateLocale,
+    /// Accept numbers, or strings of digits, as milliseconds since the Unix epoch (UTC).
+    pub accept_epoch_millis: bool,
+}
+
+/// A typed value for one of the JSON schema date formats.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateValue {
+    /// A `format: date` value.
+    Date(NaiveDate),
+    /// A `format: date-time` value.
+    DateTime(DateTime<FixedOffset>),
+    /// A `format: time` value, with its UTC offset if it had one.
+    Time(NaiveTime, Option<FixedOffset>),
+}
+
+/// Serialises the value as ISO-8601, as the JSON schema formats expect.
+impl fmt::Display for DateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DateValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            DateValue::DateTime(date_time) => {
+                f.write_str(&date_time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }
+            DateValue::Time(time, offset) => {
+                write!(f, "{}", time.format("%H:%M:%S%.f"))?;
+                match offset {
+                    Some(offset) if offset.local_minus_utc() == 0 => f.write_str("Z"),
+                    Some(offset) => write!(f, "{offset}"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// A date value found in the instance and parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoercedDate {
+    /// JSON pointer to the value within the instance.
+    pub path: String,
+    pub format: String,
+    /// The value as it appeared in the instance, before normalising.
+    pub original: Value,
+    pub value: DateValue,
+}
+
+/// A date value found in the instance that couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateCoercionFailure {
+    /// JSON pointer to the value within the instance.
+    pub path: String,
+    pub format: String,
+    pub value: Value,
+    pub message: String,
+}
+
+/// The result of `coerce_dates`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateCoercion {
+    /// The instance with every parsed date value normalised to ISO-8601. Values that failed to
+    /// parse are left unchanged.
+    pub instance: Value,
+    pub dates: Vec<CoercedDate>,
+    pub failures: Vec<DateCoercionFailure>,
+}
+
+impl DateCoercion {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Finds every value the schema describes with `format: date`, `date-time` or `time`, parses it
+/// and normalises it to ISO-8601.
+///
+/// Only the `oneOf`, `anyOf` and `if`/`then`/`else` branches the instance matches are used, so a
+/// value isn't coerced because some other variant has a date there. Where a value matches no
+/// branch of a `oneOf` or `anyOf`, such as when the format is asserted and the date isn't ISO-8601
+/// yet, all its branches are used.
+///
+/// Besides ISO-8601, dates written with slashes are read in the order of `options.locale` and,
+/// if enabled, epoch milliseconds are accepted. Values that can't be parsed are listed in
+/// `DateCoercion::failures` with their path rather than failing the whole coercion. An error is
+/// only returned if the schema can't be walked.
+pub fn coerce_dates(
+    inspector: &SchemaInspector,
+    instance: &Value,
+    options: &DateCoercionOptions,
+) -> Result<DateCoercion> {
+    let mut coercion = DateCoercion {
+        instance: instance.clone(),
+        dates: Vec::new(),
+        failures: Vec::new(),
+    };
+    let mut dates = Vec::new();
+    let mut failures = Vec::new();
+
+    inspector.visit(
+        &mut coercion.instance,
+        BranchMode::Matching,
+        &mut |walker, nodes, value, path| {
+            let mut describing = nodes.to_vec();
+            describing.extend(walker.unmatched_branches(nodes, value)?);
+            let Some(format) = describing.iter().find_map(|node| {
+                node.get("format")
+                    .and_then(Value::as_str)
+                    .filter(|format| ["date", "date-time", "time"].contains(format))
+            }) else {
+                return Ok(());
+            };
+            if !(value.is_string() || value.is_number()) {
+                return Ok(());
+            }
+
+            match parse_date_value(format, value, options) {
+                Ok(parsed) => {
+                    dates.push(CoercedDate {
+                        path: path.to_string(),
+                        format: format.to_string(),
+                        original: value.clone(),
+                        value: parsed,
+                    });
+                    *value = Value::String(parsed.to_string());
+                }
+                Err(message) => failures.push(DateCoercionFailure {
+                    path: path.to_string(),
+                    format: format.to_string(),
+                    value: value.clone(),
+                    message,
+                }),
+            }
+
+            Ok(())
+        },
+    )?;
+
+    coercion.dates = dates;
+    coercion.failures = failures;
+
+    Ok(coercion)
+}
+
+/// Parses a JSON value as one of the JSON schema date formats: `date`, `date-time` or `time`.
+pub fn parse_date_value(
+    format: &str,
+    value: &Value,
+    options: &DateCoercionOptions,
+) -> Result<DateValue, String> {
+    let epoch_millis = match value {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) if text.bytes().all(|byte| byte.is_ascii_digit()) => {
+            text.parse::<i64>().ok()
+        }
+        _ => None,
+    };
+
+    if let Some(millis) = epoch_millis.filter(|_| options.accept_epoch_millis) {
+        let date_time = Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| format!("{millis} is out of range for epoch milliseconds"))?
+            .fixed_offset();
+        return match format {
+            "date" => Ok(DateValue::Date(date_time.date_naive())),
+            "date-time" => Ok(DateValue::DateTime(date_time)),
+            _ => Ok(DateValue::Time(date_time.time(), Some(*date_time.offset()))),
+        };
+    }
+
+    let Some(text) = value.as_str() else {
+        return Err(format!("{value} is not a valid \"{format}\""));
+    };
+    let text = text.trim();
+
+    let parsed = match format {
+        "date" => parse_date(text, options.locale).map(DateValue::Date),
+        "date-time" => parse_date_time(text, options.locale).map(DateValue::DateTime),
+        "time" => parse_time(text).map(|(time, offset)| DateValue::Time(time, offset)),
+        _ => None,
+    };
+
+    parsed.ok_or_else(|| format!("\"{text}\" is not a valid \"{format}\""))
+}
+
+fn slash_date_format(locale: DateLocale) -> Option<&'static str> {
+    match locale {
+        DateLocale::Uk => Some("%d/%m/%Y"),
+        DateLocale::Us => Some("%m/%d/%Y"),
+        DateLocale::Iso => None,
+    }
+}
+
+fn parse_date(text: &str, locale: DateLocale) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .or_else(|| {
+            slash_date_format(locale)
+                .and_then(|format| NaiveDate::parse_from_str(text, format).ok())
+        })
+}
+
+fn parse_date_time(text: &str, locale: DateLocale) -> Option<DateTime<FixedOffset>> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(text) {
+        return Some(date_time);
+    }
+
+    // Date-times without an offset are taken to be UTC.
+    let mut formats = vec![
+        "%Y-%m-%dT%H:%M:%S%.f".to_string(),
+        "%Y-%m-%d %H:%M:%S%.f".to_string(),
+        "%Y-%m-%dT%H:%M".to_string(),
+    ];
+    if let Some(date_format) = slash_date_format(locale) {
+        formats.push(format!("{date_format} %H:%M:%S"));
+        formats.push(format!("{date_format} %H:%M"));
+    }
+
+    formats
+        .into_iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, &format).ok())
+        .map(|date_time| date_time.and_utc().fixed_offset())
+}
+
+fn parse_time(text: &str) -> Option<(NaiveTime, Option<FixedOffset>)> {
+    let (time, offset) = if let Some(time) = text.strip_suffix(['Z', 'z']) {
+        (time, Some(FixedOffset::east_opt(0)?))
+    } else {
+        match text.rfind(['+', '-']) {
+            Some(index) => {
+                let offset =
+                    DateTime::parse_from_rfc3339(&format!("2000-01-01T00:00:00{}", &text[index..]))
+                        .ok()?
+                        .offset()
+                        .to_owned();
+                (&text[..index], Some(offset))
+            }
+            None => (text, None),
+        }
+    };
+
+    ["%H:%M:%S%.f", "%H:%M"]
+        .into_iter()
+        .find_map(|format| NaiveTime::parse_from_str(time, format).ok())
+        .map(|time| (time, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{coerce_dates, parse_date_value, DateCoercionOptions, DateLocale, DateValue};
+    use crate::inspector::SchemaInspector;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "openedOn": { "type": "string", "format": "date" },
+                "updatedAt": { "type": "string", "format": "date-time" },
+                "callAt": { "type": "string", "format": "time" },
+                "payments": {
+                    "type": "array",
+                    "items": { "$ref": "#/definitions/payment" }
+                },
+                "note": { "type": "string" }
+            },
+            "definitions": {
+                "payment": {
+                    "type": "object",
+                    "properties": {
+                        "dueOn": { "type": ["string", "null"], "format": "date" }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn coerce_dates_normalises_every_date_format() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({
+            "openedOn": "31/01/2025",
+            "updatedAt": "2025-01-31T10:15:00+01:00",
+            "callAt": "09:30",
+            "payments": [{ "dueOn": "2025-02-28" }, { "dueOn": "01/03/2025" }, { "dueOn": null }],
+            "note": "31/01/2025"
+        });
+
+        let coercion =
+            coerce_dates(&inspector, &instance, &DateCoercionOptions::default()).unwrap();
+
+        assert!(coercion.is_success());
+        assert_eq!(
+            coercion.instance,
+            json!({
+                "openedOn": "2025-01-31",
+                "updatedAt": "2025-01-31T10:15:00+01:00",
+                "callAt": "09:30:00",
+                "payments": [{ "dueOn": "2025-02-28" }, { "dueOn": "2025-03-01" }, { "dueOn": null }],
+                "note": "31/01/2025"
+            })
+        );
+        let paths: Vec<&str> = coercion
+            .dates
+            .iter()
+            .map(|date| date.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/callAt",
+                "/openedOn",
+                "/payments/0/dueOn",
+                "/payments/1/dueOn",
+                "/updatedAt"
+            ]
+        );
+        assert_eq!(coercion.dates[1].original, json!("31/01/2025"));
+    }
+
+    #[test]
+    fn coerce_dates_reports_every_failure_with_its_path() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({
+            "openedOn": "31/02/2025",
+            "payments": [{ "dueOn": "soon" }],
+            "updatedAt": 1738318500000_i64
+        });
+
+        let coercion =
+            coerce_dates(&inspector, &instance, &DateCoercionOptions::default()).unwrap();
+
+        let failures: Vec<(&str, &str)> = coercion
+            .failures
+            .iter()
+            .map(|failure| (failure.path.as_str(), failure.message.as_str()))
+            .collect();
+        assert_eq!(
+            failures,
+            vec![
+                ("/openedOn", "\"31/02/2025\" is not a valid \"date\""),
+                ("/payments/0/dueOn", "\"soon\" is not a valid \"date\""),
+                ("/updatedAt", "1738318500000 is not a valid \"date-time\""),
+            ]
+        );
+        assert_eq!(coercion.instance, instance);
+    }
+
+    #[test]
+    fn coerce_dates_only_uses_the_branch_the_instance_matches() {
+        let inspector = SchemaInspector::new(&json!({
+            "oneOf": [
+                {
+                    "properties": {
+                        "kind": { "const": "dated" },
+                        "value": { "type": "string", "format": "date" }
+                    },
+                    "required": ["kind"]
+                },
+                {
+                    "properties": {
+                        "kind": { "const": "reference" },
+                        "value": { "type": "string" }
+                    },
+                    "required": ["kind"]
+                }
+            ]
+        }))
+        .unwrap();
+        let options = DateCoercionOptions::default();
+
+        let reference = json!({ "kind": "reference", "value": "01/02/2025" });
+        let coercion = coerce_dates(&inspector, &reference, &options).unwrap();
+        assert_eq!(coercion.instance, reference);
+        assert!(coercion.dates.is_empty());
+
+        let dated = json!({ "kind": "dated", "value": "01/02/2025" });
+        let coercion = coerce_dates(&inspector, &dated, &options).unwrap();
+        assert_eq!(
+            coercion.instance,
+            json!({ "kind": "dated", "value": "2025-02-01" })
+        );
+    }
+
+    #[test]
+    fn coerce_dates_accepts_epoch_millis_when_enabled() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({ "openedOn": 1738318500000_i64, "updatedAt": "1738318500000" });
+        let options = DateCoercionOptions {
+            accept_epoch_millis: true,
+            ..Default::default()
+        };
+
+        let coercion = coerce_dates(&inspector, &instance, &options).unwrap();
+
+        assert_eq!(
+            coercion.instance,
+            json!({ "openedOn": "2025-01-31", "updatedAt": "2025-01-31T10:15:00Z" })
+        );
+    }
+
+    #[test]
+    fn parse_date_value_reads_slashed_dates_by_locale() {
+        let uk = DateCoercionOptions::default();
+        let us = DateCoercionOptions {
+            locale: DateLocale::Us,
+            ..Default::default()
+        };
+        let iso = DateCoercionOptions {
+            locale: DateLocale::Iso,
+            ..Default::default()
+        };
+
+        let uk_date = parse_date_value("date", &json!("02/03/2025"), &uk).unwrap();
+        let us_date = parse_date_value("date", &json!("02/03/2025"), &us).unwrap();
+
+        assert_eq!(uk_date.to_string(), "2025-03-02");
+        assert_eq!(us_date.to_string(), "2025-02-03");
+        assert!(parse_date_value("date", &json!("02/03/2025"), &iso).is_err());
+        assert_eq!(
+            parse_date_value("date-time", &json!("02/03/2025 14:05"), &uk)
+                .unwrap()
+                .to_string(),
+            "2025-03-02T14:05:00Z"
+        );
+    }
+
+    #[test]
+    fn parse_date_value_keeps_time_offsets() {
+        let options = DateCoercionOptions::default();
+
+        let utc = parse_date_value("time", &json!("10:15:30z"), &options).unwrap();
+        let offset = parse_date_value("time", &json!("10:15:30.5-05:00"), &options).unwrap();
+
+        assert!(matches!(utc, DateValue::Time(_, Some(_))));
+        assert_eq!(utc.to_string(), "10:15:30Z");
+        assert_eq!(offset.to_string(), "10:15:30.500-05:00");
+    }
+}
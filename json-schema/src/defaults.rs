@@ -0,0 +1,212 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::inspector::SchemaInspector;
+use crate::util::escape_pointer_segment;
+use crate::walk::BranchMode;
+
+/// Options controlling where `apply_defaults` fills in values.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultOptions {
+    /// Apply defaults from `anyOf`, `oneOf`, `if`/`then`/`else` and `dependentSchemas` branches
+    /// the instance matches. When unset, only defaults that apply unconditionally are used.
+    pub apply_in_branches: bool,
+}
+
+/// The result of `apply_defaults`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedDefaults {
+    /// The instance with the defaults filled in.
+    pub instance: Value,
+    /// JSON pointers to the values that were filled in, in the order they were filled.
+    pub filled: Vec<String>,
+}
+
+/// Fills in the schema's `default` for every property missing from the instance.
+///
+/// Objects are filled in at every level, including inside array items and defaults that were
+/// themselves filled in, following `$ref` and `allOf`. Properties the instance already has are
+/// left unchanged, even when they are null.
+pub fn apply_defaults(
+    inspector: &SchemaInspector,
+    instance: &Value,
+    options: &DefaultOptions,
+) -> Result<AppliedDefaults> {
+    let mode = if options.apply_in_branches {
+        BranchMode::Matching
+    } else {
+        BranchMode::Skip
+    };
+    let mut instance = instance.clone();
+    let mut filled = Vec::new();
+
+    inspector.visit(&mut instance, mode, &mut |walker, nodes, value, path| {
+        let Value::Object(object) = value else {
+            return Ok(());
+        };
+
+        for node in nodes {
+            let Some(Value::Object(properties)) = node.get("properties") else {
+                continue;
+            };
+            for key in properties.keys() {
+                if object.contains_key(key) {
+                    continue;
+                }
+                let children = walker.property_nodes(std::slice::from_ref(node), key)?;
+                let default = walker
+                    .expand_all(&children, None, mode)?
+                    .iter()
+                    .find_map(|child| child.get("default"));
+
+                if let Some(default) = default {
+                    object.insert(key.clone(), default.clone());
+                    filled.push(format!("{path}/{}", escape_pointer_segment(key)));
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(AppliedDefaults { instance, filled })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{apply_defaults, DefaultOptions};
+    use crate::inspector::SchemaInspector;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "default": "OPEN" },
+                "address": { "$ref": "#/$defs/address" },
+                "contact": {
+                    "type": "object",
+                    "default": {},
+                    "properties": {
+                        "channel": { "type": "string", "default": "EMAIL" }
+                    }
+                },
+                "payments": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "amount": { "type": "number" },
+                            "currency": { "$ref": "#/$defs/currency" }
+                        }
+                    }
+                },
+                "notes": { "type": ["string", "null"], "default": "" }
+            },
+            "$defs": {
+                "address": {
+                    "type": "object",
+                    "properties": {
+                        "country": { "type": "string", "default": "GB" }
+                    }
+                },
+                "currency": { "type": "string", "default": "GBP" }
+            }
+        })
+    }
+
+    #[test]
+    fn apply_defaults_fills_missing_properties_at_every_level() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let instance = json!({
+            "address": { "line1": "1 High Street" },
+            "payments": [{ "amount": 10 }, { "amount": 20, "currency": "EUR" }],
+            "notes": null
+        });
+
+        let applied = apply_defaults(&inspector, &instance, &DefaultOptions::default()).unwrap();
+
+        assert_eq!(
+            applied.instance,
+            json!({
+                "status": "OPEN",
+                "address": { "line1": "1 High Street", "country": "GB" },
+                "contact": { "channel": "EMAIL" },
+                "payments": [
+                    { "amount": 10, "currency": "GBP" },
+                    { "amount": 20, "currency": "EUR" }
+                ],
+                "notes": null
+            })
+        );
+        assert_eq!(
+            applied.filled,
+            vec![
+                "/contact",
+                "/status",
+                "/address/country",
+                "/contact/channel",
+                "/payments/0/currency"
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_defaults_does_not_create_objects_without_defaults() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let applied = apply_defaults(&inspector, &json!({}), &DefaultOptions::default()).unwrap();
+
+        assert_eq!(
+            applied.instance,
+            json!({ "status": "OPEN", "contact": { "channel": "EMAIL" }, "notes": "" })
+        );
+    }
+
+    #[test]
+    fn apply_defaults_in_branches_is_optional() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "method": { "type": "string" }
+            },
+            "oneOf": [
+                {
+                    "properties": {
+                        "method": { "const": "CARD" },
+                        "recurring": { "type": "boolean", "default": false }
+                    },
+                    "required": ["method"]
+                },
+                {
+                    "properties": {
+                        "method": { "const": "DIRECT_DEBIT" },
+                        "collectionDay": { "type": "integer", "default": 1 }
+                    },
+                    "required": ["method"]
+                }
+            ]
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+        let instance = json!({ "method": "DIRECT_DEBIT" });
+
+        let skipped = apply_defaults(&inspector, &instance, &DefaultOptions::default()).unwrap();
+        let applied = apply_defaults(
+            &inspector,
+            &instance,
+            &DefaultOptions {
+                apply_in_branches: true,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(skipped.instance, instance);
+        assert!(skipped.filled.is_empty());
+        assert_eq!(
+            applied.instance,
+            json!({ "method": "DIRECT_DEBIT", "collectionDay": 1 })
+        );
+        assert_eq!(applied.filled, vec!["/collectionDay"]);
+    }
+}
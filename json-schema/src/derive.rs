@@ -0,0 +1,160 @@
+use anyhow::Result;
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::inspector::SchemaInspector;
+use crate::redact::SENSITIVE_KEYWORD;
+use crate::typed::{validate_into, ValidateIntoError};
+
+pub use schemars;
+
+/// The JSON schema draft to generate schemas for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaDraft {
+    #[default]
+    Draft202012,
+    Draft7,
+}
+
+/// Generates the JSON schema for a type deriving `schemars::JsonSchema`.
+///
+/// `chrono::NaiveDate` fields get `format: date` and `DateTime`s get `format: date-time`, so the
+/// inspector's date handling recognises them. Fields marked with
+/// `#[schemars(transform = flexys_json_schema::derive::sensitive)]` are redacted by `redact`.
+pub fn schema_for<T: JsonSchema + ?Sized>(draft: SchemaDraft) -> Value {
+    let settings = match draft {
+        SchemaDraft::Draft202012 => SchemaSettings::draft2020_12(),
+        SchemaDraft::Draft7 => SchemaSettings::draft07(),
+    };
+
+    settings
+        .into_generator()
+        .into_root_schema_for::<T>()
+        .to_value()
+}
+
+/// A schemars transform that marks a field's schema with `x-sensitive: true`.
+pub fn sensitive(schema: &mut Schema) {
+    schema.insert(SENSITIVE_KEYWORD.to_string(), Value::Bool(true));
+}
+
+/// An inspector for the draft 2020-12 schema generated for the type.
+pub fn inspector_for<T: JsonSchema + ?Sized>() -> Result<SchemaInspector> {
+    SchemaInspector::new(&schema_for::<T>(SchemaDraft::default()))
+}
+
+/// Validates and deserializes the value like `validate_into`, against the draft 2020-12 schema
+/// generated for `T`.
+pub fn validate_derived<T>(value: &Value) -> Result<T, ValidateIntoError>
+where
+    T: JsonSchema + DeserializeOwned,
+{
+    validate_into(&schema_for::<T>(SchemaDraft::default()), value)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use schemars::JsonSchema;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::{inspector_for, schema_for, validate_derived, SchemaDraft};
+    use crate::redact::{redact, RedactionOptions};
+    use crate::typed::ValidateIntoError;
+    use crate::util::is_date_field;
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    #[serde(rename_all = "camelCase")]
+    struct Customer {
+        reference: String,
+        date_of_birth: NaiveDate,
+        closed_on: Option<NaiveDate>,
+        #[schemars(transform = super::sensitive)]
+        name: String,
+        address: Address,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct Address {
+        #[schemars(length(min = 1))]
+        postcode: String,
+    }
+
+    #[test]
+    fn schema_for_generates_either_draft() {
+        let latest = schema_for::<Customer>(SchemaDraft::Draft202012);
+        let draft7 = schema_for::<Customer>(SchemaDraft::Draft7);
+
+        assert_eq!(
+            latest["$schema"],
+            json!("https://json-schema.org/draft/2020-12/schema")
+        );
+        assert_eq!(
+            latest["properties"]["address"]["$ref"],
+            json!("#/$defs/Address")
+        );
+        assert_eq!(
+            latest["properties"]["dateOfBirth"],
+            json!({ "type": "string", "format": "date" })
+        );
+        assert_eq!(
+            latest["properties"]["name"],
+            json!({ "type": "string", "x-sensitive": true })
+        );
+        assert_eq!(
+            draft7["$schema"],
+            json!("http://json-schema.org/draft-07/schema#")
+        );
+        assert_eq!(
+            draft7["properties"]["address"]["$ref"],
+            json!("#/definitions/Address")
+        );
+    }
+
+    #[test]
+    fn generated_schemas_work_with_the_inspector() {
+        let inspector = inspector_for::<Customer>().unwrap();
+        let instance = json!({
+            "reference": "CASE-1",
+            "dateOfBirth": "1980-06-15",
+            "closedOn": null,
+            "name": "Jane Doe",
+            "address": { "postcode": "SW1A 1AA" }
+        });
+
+        assert!(inspector.is_date_field("/dateOfBirth").unwrap());
+        assert!(inspector.is_date_field("/closedOn").unwrap());
+        assert!(!inspector.is_date_field("/reference").unwrap());
+        let schema = schema_for::<Customer>(SchemaDraft::Draft202012);
+        assert!(is_date_field(&schema, "dateOfBirth"));
+        assert!(is_date_field(&schema, "closedOn"));
+
+        let redaction = redact(&inspector, &instance, &RedactionOptions::default()).unwrap();
+        assert_eq!(redaction.redacted, vec!["/name"]);
+    }
+
+    #[test]
+    fn validate_derived_checks_the_generated_schema() {
+        let customer: Customer = validate_derived(&json!({
+            "reference": "CASE-1",
+            "dateOfBirth": "1980-06-15",
+            "name": "Jane Doe",
+            "address": { "postcode": "SW1A 1AA" }
+        }))
+        .unwrap();
+        assert_eq!(customer.closed_on, None);
+
+        let err = validate_derived::<Customer>(&json!({
+            "reference": "CASE-1",
+            "dateOfBirth": "1980-06-15",
+            "name": "Jane Doe",
+            "address": { "postcode": "" }
+        }))
+        .unwrap_err();
+        assert!(matches!(err, ValidateIntoError::Validation(_)));
+        assert_eq!(err.instance_paths(), vec!["/address/postcode"]);
+    }
+}
@@ -0,0 +1,335 @@
+use std::fmt;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::dates::{parse_date_value, DateCoercionOptions};
+use crate::inspector::SchemaInspector;
+use crate::patch::json_equal;
+use crate::util::escape_pointer_segment;
+
+/// One difference between two JSON values.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Change {
+    /// The value is only in the second JSON value.
+    Added { path: String, value: Value },
+    /// The value is only in the first JSON value.
+    Removed { path: String, value: Value },
+    /// The value differs between the two.
+    Changed {
+        path: String,
+        from: Value,
+        to: Value,
+    },
+}
+
+impl Change {
+    /// JSON pointer to the value that differs.
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "added [{path}]: {value}"),
+            Change::Removed { path, value } => write!(f, "removed [{path}]: {value}"),
+            Change::Changed { path, from, to } => write!(f, "changed [{path}]: {from} -> {to}"),
+        }
+    }
+}
+
+/// The result of `diff`: the changes that turn the first JSON value into the second.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct JsonDiff {
+    pub changes: Vec<Change>,
+}
+
+impl JsonDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The changes as an RFC 6902 JSON Patch, which applied to the first value gives one
+    /// semantically equal to the second.
+    pub fn to_json_patch(&self) -> Value {
+        self.changes
+            .iter()
+            .map(|change| match change {
+                Change::Added { path, value } => {
+                    json!({ "op": "add", "path": path, "value": value })
+                }
+                Change::Removed { path, .. } => json!({ "op": "remove", "path": path }),
+                Change::Changed { path, to, .. } => {
+                    json!({ "op": "replace", "path": path, "value": to })
+                }
+            })
+            .collect()
+    }
+}
+
+/// One change per line, or "No differences." when there are none.
+impl fmt::Display for JsonDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("No differences.");
+        }
+        for (index, change) in self.changes.iter().enumerate() {
+            if index > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{change}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares two JSON values, ignoring differences that don't change their meaning.
+///
+/// Key order never matters and numbers are compared by value, so `1.0` equals `1`. Where the
+/// schema describes a value with `format: date`, `date-time` or `time`, two representations of
+/// the same date or instant are equal, such as `15/06/2025` and `2025-06-15`, or date-times in
+/// different offsets. Dates written with slashes are read as `dd/mm/yyyy`.
+///
+/// Array items are compared by index. Removed items are listed from the end of the array, so the
+/// changes can be applied in order.
+///
+/// # Arguments
+///
+/// * `a`: The original JSON value.
+/// * `b`: The JSON value to compare it with.
+/// * `schema`: The JSON schema both values are described by.
+///
+pub fn diff(a: &Value, b: &Value, schema: &Value) -> Result<JsonDiff> {
+    let differ = Differ {
+        inspector: SchemaInspector::new(schema)?,
+        root: b,
+        options: DateCoercionOptions::default(),
+    };
+    let mut changes = Vec::new();
+    differ.diff_values(a, b, "", &mut changes)?;
+
+    Ok(JsonDiff { changes })
+}
+
+struct Differ<'a> {
+    inspector: SchemaInspector,
+    /// The second value, which the schema is matched against to find date fields.
+    root: &'a Value,
+    options: DateCoercionOptions,
+}
+
+impl Differ<'_> {
+    fn diff_values(
+        &self,
+        a: &Value,
+        b: &Value,
+        path: &str,
+        changes: &mut Vec<Change>,
+    ) -> Result<()> {
+        match (a, b) {
+            (Value::Object(a), Value::Object(b)) => {
+                for (key, a_value) in a {
+                    let child_path = format!("{path}/{}", escape_pointer_segment(key));
+                    match b.get(key) {
+                        Some(b_value) => {
+                            self.diff_values(a_value, b_value, &child_path, changes)?
+                        }
+                        None => changes.push(Change::Removed {
+                            path: child_path,
+                            value: a_value.clone(),
+                        }),
+                    }
+                }
+                for (key, b_value) in b {
+                    if !a.contains_key(key) {
+                        changes.push(Change::Added {
+                            path: format!("{path}/{}", escape_pointer_segment(key)),
+                            value: b_value.clone(),
+                        });
+                    }
+                }
+            }
+            (Value::Array(a), Value::Array(b)) => {
+                for (index, (a_item, b_item)) in a.iter().zip(b).enumerate() {
+                    self.diff_values(a_item, b_item, &format!("{path}/{index}"), changes)?;
+                }
+                for index in (b.len()..a.len()).rev() {
+                    changes.push(Change::Removed {
+                        path: format!("{path}/{index}"),
+                        value: a[index].clone(),
+                    });
+                }
+                for (index, b_item) in b.iter().enumerate().skip(a.len()) {
+                    changes.push(Change::Added {
+                        path: format!("{path}/{index}"),
+                        value: b_item.clone(),
+                    });
+                }
+            }
+            (a, b) if json_equal(a, b) || self.same_date(a, b, path)? => {}
+            (a, b) => changes.push(Change::Changed {
+                path: path.to_string(),
+                from: a.clone(),
+                to: b.clone(),
+            }),
+        }
+
+        Ok(())
+    }
+
+    /// Whether the values are the same date, date-time or time, if the schema says they're one.
+    fn same_date(&self, a: &Value, b: &Value, path: &str) -> Result<bool> {
+        if !a.is_string() || !b.is_string() {
+            return Ok(false);
+        }
+        let Some(field) = self.inspector.field_for_instance(self.root, path)? else {
+            return Ok(false);
+        };
+        let Some(format) = field
+            .format
+            .filter(|format| ["date", "date-time", "time"].contains(&format.as_str()))
+        else {
+            return Ok(false);
+        };
+
+        let parsed = (
+            parse_date_value(&format, a, &self.options),
+            parse_date_value(&format, b, &self.options),
+        );
+
+        Ok(matches!(parsed, (Ok(a), Ok(b)) if a == b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{diff, Change};
+    use crate::patch::apply_json_patch;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string" },
+                "amount": { "type": "number" },
+                "dueDate": { "type": "string", "format": "date" },
+                "createdAt": { "type": "string", "format": "date-time" },
+                "notes": { "type": "string" },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        })
+    }
+
+    #[test]
+    fn diff_ignores_key_order_numbers_and_date_representations() {
+        let a: serde_json::Value = serde_json::from_str(
+            r#"{
+                "amount": 1.0,
+                "reference": "A1",
+                "dueDate": "15/06/2025",
+                "createdAt": "2025-06-15T10:00:00+01:00"
+            }"#,
+        )
+        .unwrap();
+        let b = json!({
+            "reference": "A1",
+            "dueDate": "2025-06-15",
+            "createdAt": "2025-06-15T09:00:00Z",
+            "amount": 1
+        });
+
+        let changes = diff(&a, &b, &schema()).unwrap();
+
+        assert!(changes.is_empty(), "{changes}");
+        assert_eq!(changes.to_string(), "No differences.");
+    }
+
+    #[test]
+    fn diff_lists_added_removed_and_changed_values() {
+        let a = json!({
+            "reference": "A1",
+            "dueDate": "2025-06-15",
+            "notes": "call back",
+            "tags": ["urgent", "vip", "legacy"]
+        });
+        let b = json!({
+            "reference": "A2",
+            "dueDate": "16/06/2025",
+            "amount": 12.5,
+            "tags": ["urgent"]
+        });
+
+        let changes = diff(&a, &b, &schema()).unwrap();
+
+        assert_eq!(
+            changes.changes[0],
+            Change::Changed {
+                path: "/dueDate".to_string(),
+                from: json!("2025-06-15"),
+                to: json!("16/06/2025")
+            }
+        );
+        assert_eq!(
+            changes.to_string(),
+            "changed [/dueDate]: \"2025-06-15\" -> \"16/06/2025\"\n\
+             removed [/notes]: \"call back\"\n\
+             changed [/reference]: \"A1\" -> \"A2\"\n\
+             removed [/tags/2]: \"legacy\"\n\
+             removed [/tags/1]: \"vip\"\n\
+             added [/amount]: 12.5"
+        );
+    }
+
+    #[test]
+    fn diff_renders_as_a_json_patch() {
+        let a = json!({ "reference": "A1", "tags": ["a", "b"], "notes": "x" });
+        let b = json!({ "reference": "A1", "tags": ["a", "c", "d"], "amount": 3 });
+
+        let changes = diff(&a, &b, &schema()).unwrap();
+        let patch = changes.to_json_patch();
+
+        assert_eq!(
+            patch,
+            json!([
+                { "op": "remove", "path": "/notes" },
+                { "op": "replace", "path": "/tags/1", "value": "c" },
+                { "op": "add", "path": "/tags/2", "value": "d" },
+                { "op": "add", "path": "/amount", "value": 3 }
+            ])
+        );
+        assert_eq!(apply_json_patch(a, &patch).unwrap(), b);
+        assert_eq!(
+            serde_json::to_value(&changes.changes[0]).unwrap(),
+            json!({ "kind": "removed", "path": "/notes", "value": "x" })
+        );
+    }
+
+    #[test]
+    fn diff_compares_large_integers_exactly() {
+        let schema = json!({ "type": "object", "properties": { "id": { "type": "integer" } } });
+        let a = json!({ "id": 9007199254740993u64, "balance": 1.0 });
+        let b = json!({ "id": 9007199254740992u64, "balance": 1 });
+
+        let changes = diff(&a, &b, &schema).unwrap();
+
+        assert_eq!(
+            changes.changes,
+            vec![Change::Changed {
+                path: "/id".to_string(),
+                from: json!(9007199254740993u64),
+                to: json!(9007199254740992u64)
+            }]
+        );
+    }
+}
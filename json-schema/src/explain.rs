@@ -0,0 +1,357 @@
+use std::fmt;
+
+use anyhow::Result;
+use jsonschema::BasicOutput;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::inspector::SchemaInspector;
+use crate::report::{ValidationIssue, ValidationReport};
+use crate::util::unescape_pointer_segment;
+use crate::walk::BranchMode;
+
+/// How an instance fared against the branches of a `oneOf` or `anyOf`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "camelCase")]
+pub enum BranchOutcome {
+    /// The instance is valid against these branches, and the keyword passed.
+    Matched { branches: Vec<usize> },
+    /// The instance is valid against more than one branch of a `oneOf`, so it failed.
+    Ambiguous { branches: Vec<usize> },
+    /// The instance is valid against no branch. `branch` is the one with the fewest errors.
+    Closest { branch: usize, errors: usize },
+}
+
+/// Which branch of a `oneOf` or `anyOf` an instance value matched, or came closest to matching.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BranchExplanation {
+    /// JSON pointer to the value within the instance.
+    pub instance_path: String,
+    /// JSON pointer to the `oneOf` or `anyOf` keyword.
+    pub schema_path: String,
+    pub keyword: String,
+    #[serde(flatten)]
+    pub outcome: BranchOutcome,
+}
+
+impl fmt::Display for BranchExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at [{}] for [{}]: ",
+            self.keyword, self.schema_path, self.instance_path
+        )?;
+        match &self.outcome {
+            BranchOutcome::Matched { branches } => write!(f, "matched branch {branches:?}"),
+            BranchOutcome::Ambiguous { branches } => {
+                write!(f, "matched more than one branch {branches:?}")
+            }
+            BranchOutcome::Closest { branch, errors } => {
+                write!(
+                    f,
+                    "matched no branch, closest was {branch} with {errors} error(s)"
+                )
+            }
+        }
+    }
+}
+
+/// The result of `explain`: the validation issues, plus what happened at each `oneOf` and
+/// `anyOf` the instance went through.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Explanation {
+    /// The issues found, with only the closest branch's errors for a failed `oneOf` or `anyOf`.
+    pub report: ValidationReport,
+    pub branches: Vec<BranchExplanation>,
+}
+
+impl Explanation {
+    pub fn is_valid(&self) -> bool {
+        self.report.is_valid()
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report)?;
+        for branch in &self.branches {
+            write!(f, "\n{branch}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates the instance, explaining which branch of each `oneOf` and `anyOf` it matched.
+///
+/// Where a value matches no branch, jsonschema reports the errors from every branch, most of
+/// which are irrelevant when the payload was clearly meant to be one particular variant. Here
+/// only the errors of the closest branch are kept: the one with the fewest errors in
+/// jsonschema's evaluation output, preferring branches whose `type`, `const` or `enum` the value
+/// does match. Branches nested inside a failed branch are not explained.
+pub fn explain(inspector: &SchemaInspector, instance: &Value) -> Result<Explanation> {
+    let mut branches = Vec::new();
+    let mut walked = instance.clone();
+
+    inspector.visit(
+        &mut walked,
+        BranchMode::Matching,
+        &mut |walker, nodes, value, path| {
+            for node in nodes {
+                for keyword in ["oneOf", "anyOf"] {
+                    let subschemas = node.subschemas(keyword)?;
+                    if subschemas.is_empty() {
+                        continue;
+                    }
+
+                    // Each branch's error count, and whether the value is the wrong type or
+                    // value for it altogether, which rules it out as the intended branch.
+                    let evaluations: Vec<(bool, usize)> = subschemas
+                        .iter()
+                        .map(|branch| match walker.validator(branch) {
+                            Some(validator) => match validator.apply(value).basic() {
+                                BasicOutput::Valid(_) => (false, 0),
+                                BasicOutput::Invalid(errors) => {
+                                    let mismatched = errors.iter().any(|error| {
+                                        error.instance_location().as_str().is_empty()
+                                            && ["type", "const", "enum"].iter().any(|keyword| {
+                                                error.keyword_location().as_str()
+                                                    == format!("/{keyword}")
+                                            })
+                                    });
+                                    (mismatched, errors.len().max(1))
+                                }
+                            },
+                            None => (true, usize::MAX),
+                        })
+                        .collect();
+                    let matched: Vec<usize> = (0..evaluations.len())
+                        .filter(|index| evaluations[*index].1 == 0)
+                        .collect();
+
+                    let outcome = match matched.len() {
+                        0 => {
+                            let (branch, (_, errors)) = evaluations
+                                .iter()
+                                .enumerate()
+                                .min_by_key(|(_, evaluation)| **evaluation)
+                                .map(|(branch, evaluation)| (branch, *evaluation))
+                                .expect("There is at least one branch");
+                            BranchOutcome::Closest { branch, errors }
+                        }
+                        1 => BranchOutcome::Matched { branches: matched },
+                        _ if keyword == "oneOf" => BranchOutcome::Ambiguous { branches: matched },
+                        _ => BranchOutcome::Matched { branches: matched },
+                    };
+                    branches.push(BranchExplanation {
+                        instance_path: path.to_string(),
+                        schema_path: format!("{}/{keyword}", node.schema_path),
+                        keyword: keyword.to_string(),
+                        outcome,
+                    });
+                }
+            }
+
+            Ok(())
+        },
+    )?;
+
+    let validator = inspector.validator()?;
+    let mut report = ValidationReport::default();
+    if let BasicOutput::Invalid(errors) = validator.apply(instance).basic() {
+        report.issues = errors
+            .iter()
+            .filter(|error| {
+                is_relevant(
+                    error.keyword_location().as_str(),
+                    error.instance_location().as_str(),
+                    &branches,
+                )
+            })
+            .map(|error| {
+                let instance_path = error.instance_location().as_str().to_string();
+                let schema_path = error.keyword_location().as_str().to_string();
+                ValidationIssue {
+                    keyword: schema_path
+                        .rsplit('/')
+                        .next()
+                        .map(unescape_pointer_segment)
+                        .unwrap_or_default(),
+                    message: error.error_description().to_string(),
+                    value: instance
+                        .pointer(&instance_path)
+                        .cloned()
+                        .unwrap_or_default(),
+                    instance_path,
+                    schema_path,
+                }
+            })
+            .collect();
+    }
+
+    Ok(Explanation { report, branches })
+}
+
+/// Whether an error should be kept: it's not in a branch of a failed `oneOf` or `anyOf` other
+/// than the closest one.
+fn is_relevant(schema_path: &str, instance_path: &str, branches: &[BranchExplanation]) -> bool {
+    branches.iter().all(|explanation| {
+        let BranchOutcome::Closest { branch, .. } = explanation.outcome else {
+            return true;
+        };
+        let within_value = instance_path == explanation.instance_path
+            || instance_path.starts_with(&format!("{}/", explanation.instance_path));
+        if !within_value {
+            return true;
+        }
+        let Some(rest) = schema_path
+            .strip_prefix(explanation.schema_path.as_str())
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            return true;
+        };
+
+        rest.split('/').next() == Some(branch.to_string().as_str())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{explain, BranchOutcome};
+    use crate::inspector::SchemaInspector;
+
+    fn inspector() -> SchemaInspector {
+        SchemaInspector::new(&json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string" },
+                "payment": {
+                    "oneOf": [
+                        {
+                            "type": "object",
+                            "properties": {
+                                "kind": { "const": "card" },
+                                "last4": { "type": "string", "maxLength": 4 }
+                            },
+                            "required": ["kind", "last4"]
+                        },
+                        { "$ref": "#/$defs/bank" },
+                        { "type": "string", "enum": ["cash", "cheque"] }
+                    ]
+                },
+                "contact": { "anyOf": [{ "type": "string" }, { "type": "number" }] }
+            },
+            "$defs": {
+                "bank": {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "const": "bank" },
+                        "sortCode": { "type": "string" },
+                        "accountNumber": { "type": "string" }
+                    },
+                    "required": ["kind", "sortCode", "accountNumber"]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn explain_reports_the_matched_branches() {
+        let explanation = explain(
+            &inspector(),
+            &json!({
+                "payment": { "kind": "bank", "sortCode": "12-34-56", "accountNumber": "12345678" },
+                "contact": 7
+            }),
+        )
+        .unwrap();
+
+        assert!(explanation.is_valid());
+        let outcomes: Vec<_> = explanation
+            .branches
+            .iter()
+            .map(|branch| (branch.instance_path.as_str(), branch.outcome.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("/contact", BranchOutcome::Matched { branches: vec![1] }),
+                ("/payment", BranchOutcome::Matched { branches: vec![1] }),
+            ]
+        );
+    }
+
+    #[test]
+    fn explain_keeps_only_the_closest_branchs_errors() {
+        let explanation = explain(
+            &inspector(),
+            &json!({ "reference": 1, "payment": { "kind": "card", "last4": "12345" } }),
+        )
+        .unwrap();
+
+        assert_eq!(
+            explanation.branches[0].outcome,
+            BranchOutcome::Closest {
+                branch: 0,
+                errors: 1
+            }
+        );
+        assert_eq!(
+            explanation.report.to_string(),
+            "Json failed validation, error(s): Validation Error [\"12345\" is longer than 4 \
+             characters]. Schema Path [/properties/payment/oneOf/0/properties/last4/maxLength]. \
+             Instance Path [/payment/last4]., Validation Error [1 is not of type \"string\"]. \
+             Schema Path [/properties/reference/type]. Instance Path [/reference]."
+        );
+        assert_eq!(explanation.report.issues[0].keyword, "maxLength");
+        assert_eq!(explanation.report.issues[0].value, json!("12345"));
+    }
+
+    #[test]
+    fn explain_picks_the_closest_branch_for_each_item() {
+        let inspector = SchemaInspector::new(&json!({
+            "type": "array",
+            "items": {
+                "oneOf": [
+                    { "type": "string", "maxLength": 2 },
+                    { "type": "integer", "minimum": 10 }
+                ]
+            }
+        }))
+        .unwrap();
+
+        let explanation = explain(&inspector, &json!(["abc", 5])).unwrap();
+
+        let schema_paths: Vec<&str> = explanation
+            .report
+            .issues
+            .iter()
+            .map(|issue| issue.schema_path.as_str())
+            .collect();
+        assert_eq!(
+            schema_paths,
+            vec!["/items/oneOf/0/maxLength", "/items/oneOf/1/minimum"]
+        );
+    }
+
+    #[test]
+    fn explain_flags_a_one_of_matching_several_branches() {
+        let inspector = SchemaInspector::new(&json!({
+            "oneOf": [{ "type": "integer" }, { "minimum": 0 }, { "type": "string" }]
+        }))
+        .unwrap();
+
+        let explanation = explain(&inspector, &json!(3)).unwrap();
+
+        assert!(!explanation.is_valid());
+        assert_eq!(
+            explanation.branches[0].to_string(),
+            "oneOf at [/oneOf] for []: matched more than one branch [0, 1]"
+        );
+    }
+}
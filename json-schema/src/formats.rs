@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{anyhow, Result};
+use fancy_regex::Regex;
+use jsonschema::{ValidationOptions, Validator};
+use serde_json::Value;
+
+/// Checks whether a string is valid for a format.
+pub type FormatChecker = dyn Fn(&str) -> bool + Send + Sync;
+
+/// Active ISO-4217 currency codes, sorted for searching.
+pub(crate) const CURRENCY_CODES: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BOV", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD",
+    "CAD", "CDF", "CHE", "CHF", "CHW", "CLF", "CLP", "CNY", "COP", "COU", "CRC", "CUP", "CVE",
+    "CZK", "DJF", "DKK", "DOP", "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL",
+    "GHS", "GIP", "GMD", "GNF", "GTQ", "GYD", "HKD", "HNL", "HTG", "HUF", "IDR", "ILS", "INR",
+    "IQD", "IRR", "ISK", "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD",
+    "KYD", "KZT", "LAK", "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK",
+    "MNT", "MOP", "MRU", "MUR", "MVR", "MWK", "MXN", "MXV", "MYR", "MZN", "NAD", "NGN", "NIO",
+    "NOK", "NPR", "NZD", "OMR", "PAB", "PEN", "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON",
+    "RSD", "RUB", "RWF", "SAR", "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE", "SOS", "SRD",
+    "SSP", "STN", "SVC", "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD",
+    "TZS", "UAH", "UGX", "USD", "USN", "UYI", "UYU", "UYW", "UZS", "VED", "VES", "VND", "VUV",
+    "WST", "XAF", "XAG", "XAU", "XBA", "XBB", "XBC", "XBD", "XCD", "XCG", "XDR", "XOF", "XPD",
+    "XPF", "XPT", "XSU", "XTS", "XUA", "XXX", "YER", "ZAR", "ZMW", "ZWG",
+];
+
+static UK_POSTCODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?i)(GIR ?0AA|[A-Z]{1,2}[0-9][A-Z0-9]? ?[0-9][A-Z]{2})$")
+        .expect("UK postcode pattern is valid")
+});
+
+/// Custom `format` checkers to validate with, alongside jsonschema's built-in formats.
+///
+/// `FormatRegistry::new` comes with the domain formats we use across services:
+/// `iban`, `uk-sort-code`, `uk-account-number`, `iso-4217`, `e164` and `uk-postcode`.
+#[derive(Clone)]
+pub struct FormatRegistry {
+    formats: BTreeMap<String, Arc<FormatChecker>>,
+    reject_unknown_formats: bool,
+}
+
+impl Default for FormatRegistry {
+    fn default() -> Self {
+        FormatRegistry::new()
+    }
+}
+
+impl FormatRegistry {
+    /// A registry with the domain formats.
+    pub fn new() -> Self {
+        FormatRegistry::empty()
+            .with_format("iban", is_iban)
+            .with_format("uk-sort-code", is_uk_sort_code)
+            .with_format("uk-account-number", is_uk_account_number)
+            .with_format("iso-4217", is_currency_code)
+            .with_format("e164", is_e164)
+            .with_format("uk-postcode", is_uk_postcode)
+    }
+
+    /// A registry with no custom formats.
+    pub fn empty() -> Self {
+        FormatRegistry {
+            formats: BTreeMap::new(),
+            reject_unknown_formats: false,
+        }
+    }
+
+    /// Registers a format, replacing any format already registered with the name.
+    pub fn with_format<F>(mut self, name: &str, checker: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.formats.insert(name.to_string(), Arc::new(checker));
+        self
+    }
+
+    /// Sets whether a schema using a format that is neither built in nor registered fails to
+    /// compile. By default unknown formats are ignored.
+    pub fn reject_unknown_formats(mut self, reject: bool) -> Self {
+        self.reject_unknown_formats = reject;
+        self
+    }
+
+    /// The names of the registered formats.
+    pub fn names(&self) -> Vec<&str> {
+        self.formats.keys().map(String::as_str).collect()
+    }
+
+    /// Whether the value is valid for the registered format, or `None` if the format isn't
+    /// registered.
+    pub fn check(&self, format: &str, value: &str) -> Option<bool> {
+        self.formats.get(format).map(|checker| checker(value))
+    }
+
+    /// Adds the registered formats to the validation options and turns on format validation.
+    pub fn configure(&self, mut options: ValidationOptions) -> ValidationOptions {
+        for (name, checker) in &self.formats {
+            let checker = Arc::clone(checker);
+            options = options.with_format(name.as_str(), move |value: &str| checker(value));
+        }
+
+        options
+            .should_validate_formats(true)
+            .should_ignore_unknown_formats(!self.reject_unknown_formats)
+    }
+
+    /// Compiles a validator for the schema that checks the registered formats.
+    pub fn validator_for(&self, schema: &Value) -> Result<Validator> {
+        self.configure(jsonschema::options())
+            .build(schema)
+            .map_err(|err| anyhow!("Invalid json schema, error: {err}"))
+    }
+}
+
+/// An IBAN in its electronic form, without spaces, with valid check digits.
+pub fn is_iban(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let well_formed = (15..=34).contains(&bytes.len())
+        && bytes[..2].iter().all(u8::is_ascii_uppercase)
+        && bytes[2..4].iter().all(u8::is_ascii_digit)
+        && bytes[4..]
+            .iter()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+    if !well_formed {
+        return false;
+    }
+
+    // The check digits are valid when the IBAN, rotated by four characters, is 1 mod 97.
+    iban_remainder(&value[4..], &value[..4]) == 1
+}
+
+/// Builds a valid IBAN from its country code and basic bank account number.
+pub(crate) fn iban(country: &str, bban: &str) -> String {
+    let check_digits = 98 - iban_remainder(bban, &format!("{country}00"));
+    format!("{country}{check_digits:02}{bban}")
+}
+
+/// The remainder mod 97 of the digits and upper case letters, with letters replaced by 10 to 35.
+fn iban_remainder(first: &str, second: &str) -> u32 {
+    first
+        .bytes()
+        .chain(second.bytes())
+        .fold(0u32, |remainder, byte| match byte {
+            b'0'..=b'9' => (remainder * 10 + u32::from(byte - b'0')) % 97,
+            _ => (remainder * 100 + u32::from(byte - b'A' + 10)) % 97,
+        })
+}
+
+/// A UK sort code, either `123456` or `12-34-56`.
+pub fn is_uk_sort_code(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    match bytes.len() {
+        6 => bytes.iter().all(u8::is_ascii_digit),
+        8 => bytes.iter().enumerate().all(|(index, byte)| match index {
+            2 | 5 => *byte == b'-',
+            _ => byte.is_ascii_digit(),
+        }),
+        _ => false,
+    }
+}
+
+/// A UK bank account number of eight digits.
+pub fn is_uk_account_number(value: &str) -> bool {
+    value.len() == 8 && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// An active ISO-4217 currency code, in upper case.
+pub fn is_currency_code(value: &str) -> bool {
+    CURRENCY_CODES.binary_search(&value).is_ok()
+}
+
+/// A phone number in E.164 form: `+`, then up to 15 digits not starting with 0.
+pub fn is_e164(value: &str) -> bool {
+    value.strip_prefix('+').is_some_and(|digits| {
+        (2..=15).contains(&digits.len())
+            && !digits.starts_with('0')
+            && digits.bytes().all(|byte| byte.is_ascii_digit())
+    })
+}
+
+/// A UK postcode, in either case, with or without the space before the inward code.
+pub fn is_uk_postcode(value: &str) -> bool {
+    UK_POSTCODE.is_match(value).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        is_currency_code, is_e164, is_iban, is_uk_account_number, is_uk_postcode, is_uk_sort_code,
+        FormatRegistry, CURRENCY_CODES,
+    };
+    use crate::report::ValidationReport;
+
+    #[test]
+    fn domain_formats_accept_valid_values() {
+        assert!(is_iban("GB82WEST12345698765432"));
+        assert!(is_iban("DE89370400440532013000"));
+        assert!(is_uk_sort_code("12-34-56"));
+        assert!(is_uk_sort_code("123456"));
+        assert!(is_uk_account_number("31926819"));
+        assert!(is_currency_code("GBP"));
+        assert!(is_e164("+447700900123"));
+        assert!(is_uk_postcode("SW1A 1AA"));
+        assert!(is_uk_postcode("m11ae"));
+        assert!(is_uk_postcode("GIR 0AA"));
+    }
+
+    #[test]
+    fn domain_formats_reject_invalid_values() {
+        assert!(!is_iban("GB82WEST12345698765431"));
+        assert!(!is_iban("GB82 WEST 1234 5698 7654 32"));
+        assert!(!is_uk_sort_code("12-3456"));
+        assert!(!is_uk_sort_code("12-34-5a"));
+        assert!(!is_uk_sort_code("12--4-56"));
+        assert!(!is_uk_sort_code("1-----56"));
+        assert!(!is_uk_sort_code("12-34-5-"));
+        assert!(!is_uk_account_number("3192681"));
+        assert!(!is_currency_code("gbp"));
+        assert!(!is_currency_code("ABC"));
+        assert!(!is_e164("07700900123"));
+        assert!(!is_e164("+4477009001234567"));
+        assert!(!is_uk_postcode("SW1A 1A"));
+    }
+
+    #[test]
+    fn currency_codes_are_sorted() {
+        assert!(CURRENCY_CODES.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn validator_for_checks_domain_and_custom_formats() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "iban": { "type": "string", "format": "iban" },
+                "currency": { "type": "string", "format": "iso-4217" },
+                "reference": { "type": "string", "format": "case-reference" }
+            }
+        });
+        let formats =
+            FormatRegistry::new().with_format("case-reference", |value| value.starts_with("CASE-"));
+        let validator = formats.validator_for(&schema).unwrap();
+
+        let valid =
+            json!({ "iban": "GB82WEST12345698765432", "currency": "EUR", "reference": "CASE-1" });
+        let invalid =
+            json!({ "iban": "GB00WEST12345698765432", "currency": "EURO", "reference": "1" });
+
+        assert!(validator.is_valid(&valid));
+        let report = ValidationReport::collect(&validator, &invalid, None);
+        let paths: Vec<&str> = report
+            .issues
+            .iter()
+            .map(|issue| issue.instance_path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["/currency", "/iban", "/reference"]);
+        assert!(report.issues.iter().all(|issue| issue.keyword == "format"));
+    }
+
+    #[test]
+    fn unknown_formats_can_be_rejected() {
+        let schema = json!({ "type": "string", "format": "sort-code" });
+
+        assert!(FormatRegistry::new().validator_for(&schema).is_ok());
+
+        let err = FormatRegistry::new()
+            .reject_unknown_formats(true)
+            .validator_for(&schema)
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Invalid json schema, error: "));
+        assert!(err.to_string().contains("sort-code"));
+    }
+}
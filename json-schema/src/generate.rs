@@ -0,0 +1,786 @@
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate};
+use rand::distr::Alphanumeric;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde_json::{json, Map, Number, Value};
+
+use crate::formats::{iban, CURRENCY_CODES};
+use crate::inspector::{enum_values, field_types, SchemaInspector};
+use crate::util::{escape_pointer_segment, remove_path, set_path};
+use crate::walk::{BranchMode, SchemaNode, SchemaWalker};
+
+/// Options controlling the instances `generate_instance` produces.
+#[derive(Debug, Clone)]
+pub struct GeneratorOptions {
+    /// Seeds every random choice, so a seed and schema always produce the same instance.
+    pub seed: u64,
+    /// Include properties that aren't required.
+    pub include_optional: bool,
+    /// Depth beyond which optional properties are left out and arrays are kept to their minimum
+    /// length, so that recursive schemas produce finite instances.
+    pub max_depth: usize,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            seed: 0,
+            include_optional: true,
+            max_depth: 4,
+        }
+    }
+}
+
+/// An instance that breaks a single constraint of the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidInstance {
+    /// JSON pointer to the value that breaks the constraint.
+    pub path: String,
+    /// The schema keyword that rejects the value.
+    pub keyword: String,
+    pub instance: Value,
+}
+
+/// The longest string or array `generate_invalid_instances` builds to break `maxLength` or
+/// `maxItems`.
+pub const MAX_VIOLATION_LENGTH: usize = 10_000;
+
+/// Generates an example instance of the schema, for fixtures and tests.
+///
+/// Types, `enum` and `const`, numeric and length bounds, `pattern`, `required` and `format` are
+/// respected, including `date`, `date-time` and the domain formats in `FormatRegistry`. `$ref`s
+/// and `allOf` are followed and one branch of each `anyOf` and `oneOf` is chosen. The result is
+/// a best effort: schemas whose constraints interact in ways the generator doesn't model may
+/// produce instances that fail validation.
+pub fn generate_instance(inspector: &SchemaInspector, options: &GeneratorOptions) -> Result<Value> {
+    inspector.walk(|walker, root| {
+        let mut generator = Generator {
+            walker,
+            rng: ChaCha8Rng::seed_from_u64(options.seed),
+            options,
+        };
+        generator.generate(vec![root], 0)
+    })
+}
+
+/// Generates instances that each break one constraint of the schema, for negative tests.
+///
+/// A valid instance is generated as by `generate_instance`, then one copy is made for every
+/// constraint that applies to one of its values, with that value changed to break it: a value of
+/// the wrong type, a string too short, a number too large, a required property removed and so
+/// on. Copies that the schema still accepts are left out, so every instance returned fails
+/// validation. `maxLength` and `maxItems` above `MAX_VIOLATION_LENGTH` aren't broken, since the
+/// value breaking them would be too large to build.
+pub fn generate_invalid_instances(
+    inspector: &SchemaInspector,
+    options: &GeneratorOptions,
+) -> Result<Vec<InvalidInstance>> {
+    let valid = generate_instance(inspector, options)?;
+    let validator = inspector.validator()?;
+
+    let mut mutations = Vec::new();
+    let mut walked = valid.clone();
+    inspector.visit(
+        &mut walked,
+        BranchMode::Matching,
+        &mut |walker, nodes, value, path| {
+            for node in nodes {
+                mutations.extend(violations(walker, node, value, path));
+            }
+            Ok(())
+        },
+    )?;
+
+    let mut seen = HashSet::new();
+    let mut invalid = Vec::new();
+    for mutation in mutations {
+        if !seen.insert((mutation.path.clone(), mutation.keyword)) {
+            continue;
+        }
+        let mut instance = valid.clone();
+        if mutation.apply(&mut instance) && !validator.is_valid(&instance) {
+            invalid.push(InvalidInstance {
+                path: mutation.path,
+                keyword: mutation.keyword.to_string(),
+                instance,
+            });
+        }
+    }
+
+    Ok(invalid)
+}
+
+struct Generator<'s, 'r> {
+    walker: &'s SchemaWalker<'r>,
+    rng: ChaCha8Rng,
+    options: &'s GeneratorOptions,
+}
+
+impl<'r> Generator<'_, 'r> {
+    fn generate(&mut self, nodes: Vec<SchemaNode<'r>>, depth: usize) -> Result<Value> {
+        let nodes = self.choose_branches(nodes)?;
+        let refs: Vec<&SchemaNode> = nodes.iter().collect();
+
+        if let Some(values) = enum_values(&refs, &[]) {
+            return Ok(values.choose(&mut self.rng).cloned().unwrap_or(Value::Null));
+        }
+
+        let types = field_types(&refs, &[]);
+        let field_type = types
+            .iter()
+            .map(String::as_str)
+            .find(|field_type| *field_type != "null")
+            .or(types.first().map(String::as_str))
+            .unwrap_or_else(|| infer_type(&refs));
+
+        Ok(match field_type {
+            "object" => self.object(&nodes, depth)?,
+            "array" => self.array(&nodes, depth)?,
+            "integer" => self.number(&refs, true),
+            "number" => self.number(&refs, false),
+            "boolean" => Value::Bool(self.rng.random_bool(0.5)),
+            "null" => Value::Null,
+            _ => Value::String(self.string(&refs)),
+        })
+    }
+
+    /// Expands the nodes, picking one branch of every `anyOf` and `oneOf`. Conditional
+    /// `if`/`then`/`else` branches are left out.
+    fn choose_branches(&mut self, nodes: Vec<SchemaNode<'r>>) -> Result<Vec<SchemaNode<'r>>> {
+        let mut expanded = self.walker.expand_all(&nodes, None, BranchMode::Skip)?;
+
+        let mut index = 0;
+        while index < expanded.len() {
+            for keyword in ["anyOf", "oneOf"] {
+                let branches = expanded[index].subschemas(keyword)?;
+                if let Some(branch) = branches.choose(&mut self.rng) {
+                    let chosen = self.walker.expand(branch, None, BranchMode::Skip)?;
+                    expanded.extend(chosen);
+                }
+            }
+            index += 1;
+        }
+
+        Ok(expanded)
+    }
+
+    fn object(&mut self, nodes: &[SchemaNode<'r>], depth: usize) -> Result<Value> {
+        let required: BTreeSet<&str> = nodes
+            .iter()
+            .filter_map(|node| node.get("required").and_then(Value::as_array))
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let mut properties: BTreeSet<&str> = nodes
+            .iter()
+            .filter_map(|node| node.get("properties").and_then(Value::as_object))
+            .flat_map(|properties| properties.keys().map(String::as_str))
+            .collect();
+        properties.extend(&required);
+
+        let mut object = Map::new();
+        for property in properties {
+            let optional = self.options.include_optional && depth < self.options.max_depth;
+            if required.contains(property) || optional {
+                let children = self.walker.property_nodes(nodes, property)?;
+                let value = self.generate(children, depth + 1)?;
+                object.insert(property.to_string(), value);
+            }
+        }
+
+        Ok(Value::Object(object))
+    }
+
+    fn array(&mut self, nodes: &[SchemaNode<'r>], depth: usize) -> Result<Value> {
+        let refs: Vec<&SchemaNode> = nodes.iter().collect();
+        let min_items = bound(&refs, "minItems", f64::max).map_or(0, |min| min as usize);
+        let max_items = bound(&refs, "maxItems", f64::min).map(|max| max as usize);
+        let unique = refs
+            .iter()
+            .any(|node| node.get("uniqueItems") == Some(&Value::Bool(true)));
+
+        let length = if depth < self.options.max_depth {
+            let shortest = min_items.max(1);
+            let longest = (shortest + 2).min(max_items.unwrap_or(usize::MAX));
+            self.rng.random_range(shortest.min(longest)..=longest)
+        } else {
+            min_items
+        };
+
+        let mut items = Vec::with_capacity(length);
+        for index in 0..length {
+            let children = self.walker.item_nodes(nodes, index)?;
+            let mut item = self.generate(children.clone(), depth + 1)?;
+            for _ in 0..10 {
+                if !unique || !items.contains(&item) {
+                    break;
+                }
+                item = self.generate(children.clone(), depth + 1)?;
+            }
+            items.push(item);
+        }
+
+        Ok(Value::Array(items))
+    }
+
+    fn number(&mut self, nodes: &[&SchemaNode], integer: bool) -> Value {
+        let minimum = bound(nodes, "minimum", f64::max);
+        let maximum = bound(nodes, "maximum", f64::min);
+        let exclusive_minimum = bound(nodes, "exclusiveMinimum", f64::max);
+        let exclusive_maximum = bound(nodes, "exclusiveMaximum", f64::min);
+        let multiple_of = bound(nodes, "multipleOf", f64::max).filter(|step| *step > 0.0);
+
+        // Exclusive bounds are kept clear of by one for integers and multiples, and by a small
+        // margin otherwise.
+        let step = multiple_of.unwrap_or(if integer { 1.0 } else { 0.01 });
+        let low = [minimum, exclusive_minimum.map(|bound| bound + step)]
+            .into_iter()
+            .flatten()
+            .reduce(f64::max);
+        let high = [maximum, exclusive_maximum.map(|bound| bound - step)]
+            .into_iter()
+            .flatten()
+            .reduce(f64::min);
+        let (low, high) = match (low, high) {
+            (Some(low), Some(high)) => (low, high.max(low)),
+            (Some(low), None) => (low, low + 100.0),
+            (None, Some(high)) => (high - 100.0, high),
+            (None, None) => (0.0, 100.0),
+        };
+
+        if integer || multiple_of.is_some() {
+            let step = if integer { step.max(1.0) } else { step };
+            let first = (low / step).ceil() as i64;
+            let last = ((high / step).floor() as i64).max(first);
+            let multiple = self.rng.random_range(first..=last) as f64 * step;
+            if integer || multiple.fract() == 0.0 {
+                return json!(multiple as i64);
+            }
+            return Number::from_f64(multiple).map_or(Value::Null, Value::Number);
+        }
+
+        let value = (self.rng.random_range(low..=high) * 100.0).round() / 100.0;
+        Number::from_f64(value.clamp(low, high)).map_or(Value::Null, Value::Number)
+    }
+
+    fn string(&mut self, nodes: &[&SchemaNode]) -> String {
+        let keyword = |keyword| nodes.iter().find_map(|node| node.get(keyword)?.as_str());
+
+        if let Some(value) = keyword("format").and_then(|format| self.format(format)) {
+            return value;
+        }
+
+        let min_length = bound(nodes, "minLength", f64::max).map_or(0, |min| min as usize);
+        let max_length = bound(nodes, "maxLength", f64::min).map(|max| max as usize);
+        let fits = |value: &str| {
+            let length = value.chars().count();
+            length >= min_length && max_length.is_none_or(|max| length <= max)
+        };
+
+        if let Some(pattern) = keyword("pattern") {
+            let unanchored = pattern
+                .strip_prefix('^')
+                .unwrap_or(pattern)
+                .trim_end_matches('$');
+            if let Ok(regex) = rand_regex::Regex::compile(unanchored, 8) {
+                for _ in 0..20 {
+                    let value: String = self.rng.sample(&regex);
+                    if fits(&value) && self.walker.pattern_matches(pattern, &value) {
+                        return value;
+                    }
+                }
+            }
+        }
+
+        let shortest = min_length.max(1).min(max_length.unwrap_or(usize::MAX));
+        let longest = max_length
+            .unwrap_or(usize::MAX)
+            .min(shortest.max(8))
+            .max(shortest);
+        let length = self.rng.random_range(shortest..=longest);
+        (&mut self.rng)
+            .sample_iter(Alphanumeric)
+            .take(length)
+            .map(char::from)
+            .collect()
+    }
+
+    fn format(&mut self, format: &str) -> Option<String> {
+        let rng = &mut self.rng;
+        let digits = |rng: &mut ChaCha8Rng, count: usize| -> String {
+            (0..count)
+                .map(|_| char::from(b'0' + rng.random_range(0..10)))
+                .collect()
+        };
+        let letters = |rng: &mut ChaCha8Rng, count: usize| -> String {
+            (0..count)
+                .map(|_| char::from(b'A' + rng.random_range(0..26)))
+                .collect()
+        };
+        let date =
+            NaiveDate::from_ymd_opt(2000, 1, 1)? + Duration::days(rng.random_range(0..10_000));
+        let seconds = rng.random_range(0..86_400);
+        let time = format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+
+        Some(match format {
+            "date" => date.format("%Y-%m-%d").to_string(),
+            "date-time" => format!("{}T{time}Z", date.format("%Y-%m-%d")),
+            "time" => format!("{time}Z"),
+            "email" | "idn-email" => format!("user{}@example.com", digits(rng, 4)),
+            "hostname" | "idn-hostname" => format!("host{}.example.com", digits(rng, 4)),
+            "uri" | "iri" => format!("https://example.com/{}", digits(rng, 6)),
+            "uri-reference" | "iri-reference" => format!("/{}", digits(rng, 6)),
+            "ipv4" => (0..4)
+                .map(|_| rng.random_range(1..=254).to_string())
+                .collect::<Vec<_>>()
+                .join("."),
+            "ipv6" => format!("2001:db8::{:x}", rng.random_range(1..=0xffff)),
+            "uuid" => {
+                let hex = format!("{:032x}", rng.random::<u128>());
+                format!(
+                    "{}-{}-4{}-a{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[13..16],
+                    &hex[17..20],
+                    &hex[20..]
+                )
+            }
+            "iban" => iban("GB", &format!("{}{}", letters(rng, 4), digits(rng, 14))),
+            "uk-sort-code" => {
+                let code = digits(rng, 6);
+                format!("{}-{}-{}", &code[..2], &code[2..4], &code[4..])
+            }
+            "uk-account-number" => digits(rng, 8),
+            "iso-4217" => CURRENCY_CODES.choose(rng)?.to_string(),
+            "e164" => format!("+447{}", digits(rng, 9)),
+            "uk-postcode" => format!(
+                "{}{} {}{}",
+                letters(rng, 2),
+                digits(rng, 1),
+                digits(rng, 1),
+                letters(rng, 2)
+            ),
+            _ => return None,
+        })
+    }
+}
+
+/// Guesses the type a schema without `type` describes from its other keywords.
+fn infer_type(nodes: &[&SchemaNode]) -> &'static str {
+    let has = |keywords: &[&str]| {
+        nodes
+            .iter()
+            .any(|node| keywords.iter().any(|keyword| node.get(keyword).is_some()))
+    };
+
+    if has(&[
+        "properties",
+        "required",
+        "additionalProperties",
+        "patternProperties",
+    ]) {
+        "object"
+    } else if has(&["items", "prefixItems", "minItems", "maxItems"]) {
+        "array"
+    } else if has(&[
+        "minimum",
+        "maximum",
+        "exclusiveMinimum",
+        "exclusiveMaximum",
+        "multipleOf",
+    ]) {
+        "number"
+    } else {
+        "string"
+    }
+}
+
+/// The tightest numeric value of the keyword across the nodes.
+fn bound(nodes: &[&SchemaNode], keyword: &str, tightest: fn(f64, f64) -> f64) -> Option<f64> {
+    nodes
+        .iter()
+        .filter_map(|node| node.get(keyword)?.as_f64())
+        .reduce(tightest)
+}
+
+/// A change to a valid instance that breaks one constraint.
+struct Mutation {
+    path: String,
+    keyword: &'static str,
+    /// JSON pointer to the value to change.
+    target: String,
+    /// The value to put at the target, or `None` to remove it.
+    value: Option<Value>,
+}
+
+impl Mutation {
+    fn replace(path: &str, keyword: &'static str, value: Value) -> Self {
+        Mutation {
+            path: path.to_string(),
+            keyword,
+            target: path.to_string(),
+            value: Some(value),
+        }
+    }
+
+    /// Applies the change, returning false if the target couldn't be reached.
+    fn apply(&self, instance: &mut Value) -> bool {
+        match &self.value {
+            Some(value) => set_path(instance, &self.target, value.clone()).is_ok(),
+            None => matches!(remove_path(instance, &self.target), Ok(Some(_))),
+        }
+    }
+}
+
+/// The changes to the value that each break one of the node's constraints.
+fn violations(
+    walker: &SchemaWalker,
+    node: &SchemaNode,
+    value: &Value,
+    path: &str,
+) -> Vec<Mutation> {
+    let mut mutations = Vec::new();
+    let number = |keyword| node.get(keyword).and_then(Value::as_f64);
+    let numeric = |number: f64| {
+        if number.fract() == 0.0 {
+            json!(number as i64)
+        } else {
+            json!(number)
+        }
+    };
+
+    let types = node.types();
+    if !types.is_empty() {
+        let wrong_type = [
+            json!("invalid"),
+            json!(1.5),
+            json!(true),
+            json!({}),
+            Value::Null,
+        ]
+        .into_iter()
+        .find(|candidate| !types.iter().any(|allowed| is_type(candidate, allowed)));
+        if let Some(wrong_type) = wrong_type {
+            mutations.push(Mutation::replace(path, "type", wrong_type));
+        }
+    }
+
+    if let Some(Value::Array(values)) = node.get("enum") {
+        let outside = [json!("invalid"), json!(-1), json!(false)]
+            .into_iter()
+            .find(|candidate| !values.contains(candidate));
+        if let Some(outside) = outside {
+            mutations.push(Mutation::replace(path, "enum", outside));
+        }
+    }
+    if let Some(constant) = node.get("const") {
+        let other = match constant {
+            Value::String(text) => json!(format!("{text}-invalid")),
+            Value::Bool(flag) => json!(!flag),
+            Value::Null => json!("invalid"),
+            _ => Value::Null,
+        };
+        mutations.push(Mutation::replace(path, "const", other));
+    }
+
+    match value {
+        Value::String(_) => {
+            if let Some(min) = number("minLength").filter(|min| *min >= 1.0) {
+                let short = "a".repeat((min as usize - 1).min(MAX_VIOLATION_LENGTH));
+                mutations.push(Mutation::replace(path, "minLength", json!(short)));
+            }
+            if let Some(length) = number("maxLength").and_then(past) {
+                let long = "a".repeat(length);
+                mutations.push(Mutation::replace(path, "maxLength", json!(long)));
+            }
+            if let Some(pattern) = node.get("pattern").and_then(Value::as_str) {
+                let mismatch = ["", "!", "~invalid~", "0"]
+                    .into_iter()
+                    .find(|candidate| !walker.pattern_matches(pattern, candidate));
+                if let Some(mismatch) = mismatch {
+                    mutations.push(Mutation::replace(path, "pattern", json!(mismatch)));
+                }
+            }
+            if let Some(format) = node.get("format").and_then(Value::as_str) {
+                let invalid = format!("not-a-{format}");
+                mutations.push(Mutation::replace(path, "format", json!(invalid)));
+            }
+        }
+        Value::Number(_) => {
+            if let Some(min) = number("minimum") {
+                mutations.push(Mutation::replace(path, "minimum", numeric(min - 1.0)));
+            }
+            if let Some(min) = number("exclusiveMinimum") {
+                mutations.push(Mutation::replace(path, "exclusiveMinimum", numeric(min)));
+            }
+            if let Some(max) = number("maximum") {
+                mutations.push(Mutation::replace(path, "maximum", numeric(max + 1.0)));
+            }
+            if let Some(max) = number("exclusiveMaximum") {
+                mutations.push(Mutation::replace(path, "exclusiveMaximum", numeric(max)));
+            }
+            if let (Some(step), Some(current)) = (number("multipleOf"), value.as_f64()) {
+                let between = current + step / 2.0;
+                mutations.push(Mutation::replace(path, "multipleOf", numeric(between)));
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = number("minItems").filter(|min| *min >= 1.0) {
+                let short = items[..(min as usize - 1).min(items.len())].to_vec();
+                mutations.push(Mutation::replace(path, "minItems", Value::Array(short)));
+            }
+            if let Some(length) = number("maxItems").and_then(past) {
+                let filler = items.last().cloned().unwrap_or(Value::Null);
+                let mut long = items.clone();
+                long.resize(length, filler);
+                mutations.push(Mutation::replace(path, "maxItems", Value::Array(long)));
+            }
+            if node.get("uniqueItems") == Some(&Value::Bool(true)) && !items.is_empty() {
+                let mut repeated = items.clone();
+                repeated.push(items[0].clone());
+                mutations.push(Mutation::replace(
+                    path,
+                    "uniqueItems",
+                    Value::Array(repeated),
+                ));
+            }
+        }
+        Value::Object(object) => {
+            let required = node.get("required").and_then(Value::as_array);
+            for property in required.into_iter().flatten().filter_map(Value::as_str) {
+                if object.contains_key(property) {
+                    let target = format!("{path}/{}", escape_pointer_segment(property));
+                    mutations.push(Mutation {
+                        path: target.clone(),
+                        keyword: "required",
+                        target,
+                        value: None,
+                    });
+                }
+            }
+            if node.get("additionalProperties") == Some(&Value::Bool(false)) {
+                let target = format!("{path}/unexpectedProperty");
+                mutations.push(Mutation {
+                    path: target.clone(),
+                    keyword: "additionalProperties",
+                    target,
+                    value: Some(json!("unexpected")),
+                });
+            }
+        }
+        _ => {}
+    }
+
+    mutations
+}
+
+/// The length that breaks a `maxLength` or `maxItems` of `max`, unless it's too long to build.
+fn past(max: f64) -> Option<usize> {
+    (max as usize)
+        .checked_add(1)
+        .filter(|length| *length <= MAX_VIOLATION_LENGTH)
+}
+
+fn is_type(value: &Value, field_type: &str) -> bool {
+    match field_type {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{generate_instance, generate_invalid_instances, GeneratorOptions};
+    use crate::formats::FormatRegistry;
+    use crate::inspector::SchemaInspector;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string", "pattern": "^CASE-[0-9]{6}$" },
+                "status": { "enum": ["OPEN", "CLOSED"] },
+                "openedOn": { "type": "string", "format": "date" },
+                "balance": { "type": "number", "minimum": 0, "maximum": 5000 },
+                "instalments": { "type": "integer", "exclusiveMinimum": 0, "maximum": 12 },
+                "iban": { "type": "string", "format": "iban" },
+                "debtor": { "$ref": "#/$defs/debtor" },
+                "payments": {
+                    "type": "array",
+                    "minItems": 1,
+                    "maxItems": 3,
+                    "items": { "$ref": "#/$defs/payment" }
+                },
+                "notes": { "type": ["string", "null"], "minLength": 2, "maxLength": 5 }
+            },
+            "required": ["reference", "status", "debtor"],
+            "$defs": {
+                "debtor": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "minLength": 1 },
+                        "email": { "type": "string", "format": "email" },
+                        "contact": {
+                            "oneOf": [
+                                { "type": "string", "format": "e164" },
+                                { "type": "string", "format": "uk-postcode" }
+                            ]
+                        }
+                    },
+                    "required": ["name"],
+                    "additionalProperties": false
+                },
+                "payment": {
+                    "type": "object",
+                    "properties": {
+                        "amount": { "type": "number", "multipleOf": 0.5, "minimum": 1 },
+                        "currency": { "type": "string", "format": "iso-4217" }
+                    },
+                    "required": ["amount", "currency"]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn generate_instance_produces_valid_instances() {
+        let schema = schema();
+        let inspector = SchemaInspector::new(&schema).unwrap();
+        let validator = FormatRegistry::new().validator_for(&schema).unwrap();
+
+        for seed in 0..20 {
+            let options = GeneratorOptions {
+                seed,
+                ..Default::default()
+            };
+            let instance = generate_instance(&inspector, &options).unwrap();
+
+            assert!(validator.is_valid(&instance), "seed {seed}: {instance:#}");
+        }
+    }
+
+    #[test]
+    fn generate_instance_is_reproducible_from_the_seed() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let options = GeneratorOptions {
+            seed: 42,
+            ..Default::default()
+        };
+
+        let first = generate_instance(&inspector, &options).unwrap();
+        let second = generate_instance(&inspector, &options).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn generate_instance_can_leave_out_optional_properties() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let options = GeneratorOptions {
+            include_optional: false,
+            ..Default::default()
+        };
+
+        let instance = generate_instance(&inspector, &options).unwrap();
+
+        let keys: Vec<&String> = instance.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["debtor", "reference", "status"]);
+        assert_eq!(instance["debtor"].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn generate_instance_terminates_for_recursive_schemas() {
+        let schema = json!({
+            "$defs": {
+                "node": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "children": { "type": "array", "items": { "$ref": "#/$defs/node" } }
+                    },
+                    "required": ["name"]
+                }
+            },
+            "$ref": "#/$defs/node"
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+
+        let instance = generate_instance(&inspector, &GeneratorOptions::default()).unwrap();
+
+        assert!(inspector.is_valid(&instance).unwrap());
+    }
+
+    #[test]
+    fn generate_invalid_instances_breaks_one_constraint_each() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let invalid = generate_invalid_instances(&inspector, &GeneratorOptions::default()).unwrap();
+
+        let broken: Vec<(&str, &str)> = invalid
+            .iter()
+            .map(|found| (found.path.as_str(), found.keyword.as_str()))
+            .collect();
+        for expected in [
+            ("", "type"),
+            ("/reference", "required"),
+            ("/reference", "pattern"),
+            ("/status", "enum"),
+            ("/balance", "minimum"),
+            ("/balance", "maximum"),
+            ("/instalments", "exclusiveMinimum"),
+            ("/debtor/name", "required"),
+            ("/debtor/name", "minLength"),
+            ("/debtor/unexpectedProperty", "additionalProperties"),
+            ("/payments", "minItems"),
+            ("/payments", "maxItems"),
+            ("/payments/0/amount", "multipleOf"),
+        ] {
+            assert!(broken.contains(&expected), "{expected:?} not in {broken:?}");
+        }
+        for found in &invalid {
+            assert!(!inspector.is_valid(&found.instance).unwrap());
+        }
+    }
+
+    #[test]
+    fn generate_invalid_instances_skips_bounds_too_large_to_break() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "code": { "type": "string", "maxLength": 4000000000u64 },
+                "notes": { "type": "string", "maxLength": u64::MAX },
+                "tags": { "type": "array", "items": { "type": "string" }, "maxItems": u64::MAX },
+                "name": { "type": "string", "maxLength": 3 }
+            },
+            "required": ["code", "notes", "tags", "name"]
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+
+        let invalid = generate_invalid_instances(&inspector, &GeneratorOptions::default()).unwrap();
+
+        let too_long: Vec<&str> = invalid
+            .iter()
+            .filter(|found| ["maxLength", "maxItems"].contains(&found.keyword.as_str()))
+            .map(|found| found.path.as_str())
+            .collect();
+        assert_eq!(too_long, vec!["/name"]);
+    }
+}
@@ -0,0 +1,567 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use jsonschema::{Registry, Validator};
+use serde_json::{Map, Value};
+
+use crate::registry::{absolute_uri, schema_uri, SchemaRegistry, SCHEMA_BASE_URI};
+use crate::util::unescape_pointer_segment;
+use crate::walk::{visit_instance, BranchMode, SchemaNode, SchemaWalker};
+
+/// Keywords that only wire schemas together, so are left out of an effective schema.
+const STRUCTURAL_KEYWORDS: [&str; 16] = [
+    "$anchor",
+    "$defs",
+    "$dynamicAnchor",
+    "$dynamicRef",
+    "$id",
+    "$recursiveAnchor",
+    "$recursiveRef",
+    "$ref",
+    "$schema",
+    "allOf",
+    "anyOf",
+    "definitions",
+    "else",
+    "if",
+    "oneOf",
+    "then",
+];
+
+/// What a schema says about the field at a JSON pointer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    /// The JSON types the field may take. Empty if the schema doesn't restrict it.
+    pub types: Vec<String>,
+    pub format: Option<String>,
+    /// Whether the field may be `null`, through its types, a `null` branch or `nullable: true`.
+    pub nullable: bool,
+    /// Whether the field's parent object requires it. The root is always required.
+    pub required: bool,
+    /// The values the field is limited to by `enum` or `const`, if any.
+    pub enum_values: Option<Vec<Value>>,
+    pub default: Option<Value>,
+    /// The keywords of every schema that unconditionally applies to the field, merged into one.
+    /// Keywords that only apply under an `anyOf`, `oneOf` or `if` branch are not included.
+    pub schema: Value,
+}
+
+/// Answers questions about the fields a JSON schema describes, looking through `$ref`s, `allOf`,
+/// `anyOf`, `oneOf` and `if`/`then`/`else` to find the effective sub-schema for a field.
+///
+/// Fields are identified by JSON pointers into an instance of the schema, so `/address/postcode`
+/// is the `postcode` property of the `address` property and `/contacts/0` is the first item of
+/// the `contacts` array.
+pub struct SchemaInspector {
+    registry: Registry,
+    root_uri: String,
+}
+
+impl SchemaInspector {
+    pub fn new(schema: &Value) -> Result<Self> {
+        Self::with_registry(schema, &SchemaRegistry::new())
+    }
+
+    /// Creates an inspector for a schema whose references point into the registry.
+    pub fn with_registry(schema: &Value, registry: &SchemaRegistry) -> Result<Self> {
+        let root_uri = schema_uri(schema, SCHEMA_BASE_URI);
+        let registry = registry.build_registry(Some((&root_uri, schema)))?;
+
+        Ok(SchemaInspector { registry, root_uri })
+    }
+
+    /// Creates an inspector for the schema registered under the URI, resolving its relative
+    /// references against that URI.
+    pub fn for_uri(registry: &SchemaRegistry, uri: &str) -> Result<Self> {
+        let schema = registry
+            .get(uri)
+            .ok_or_else(|| anyhow!("No schema registered for {uri}"))?;
+        let root_uri = schema_uri(schema, &absolute_uri(uri));
+        let registry = registry.build_registry(None)?;
+
+        Ok(SchemaInspector { registry, root_uri })
+    }
+
+    /// Describes the field at the pointer, or `None` if the schema doesn't describe it.
+    pub fn field(&self, pointer: &str) -> Result<Option<FieldSchema>> {
+        self.describe(pointer, None)
+    }
+
+    /// Describes the field at the pointer within a particular instance. Unlike `field`, only the
+    /// `anyOf`, `oneOf` and `if`/`then`/`else` branches the instance is valid against are
+    /// considered, and they are treated as applying unconditionally.
+    pub fn field_for_instance(
+        &self,
+        instance: &Value,
+        pointer: &str,
+    ) -> Result<Option<FieldSchema>> {
+        self.describe(pointer, Some(instance))
+    }
+
+    fn describe(&self, pointer: &str, instance: Option<&Value>) -> Result<Option<FieldSchema>> {
+        let walker = SchemaWalker::new(&self.registry);
+        let Some((nodes, required)) = self.resolve(&walker, pointer, instance)? else {
+            return Ok(None);
+        };
+
+        let (unconditional, conditional): (Vec<_>, Vec<_>) =
+            nodes.iter().partition(|node| !node.conditional);
+
+        let types = field_types(&unconditional, &conditional);
+        let nullable = types.iter().any(|field_type| field_type == "null")
+            || nodes
+                .iter()
+                .any(|node| node.get("nullable") == Some(&Value::Bool(true)));
+        let format = first_keyword(&unconditional, &conditional, "format")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let default = first_keyword(&unconditional, &conditional, "default").cloned();
+
+        Ok(Some(FieldSchema {
+            types,
+            format,
+            nullable,
+            required,
+            enum_values: enum_values(&unconditional, &conditional),
+            default,
+            schema: merge_schemas(&unconditional),
+        }))
+    }
+
+    /// The effective sub-schema for the field at the pointer, see `FieldSchema::schema`.
+    pub fn effective_schema(&self, pointer: &str) -> Result<Option<Value>> {
+        Ok(self.field(pointer)?.map(|field| field.schema))
+    }
+
+    /// Whether the field at the pointer is a string with the format "date".
+    pub fn is_date_field(&self, pointer: &str) -> Result<bool> {
+        Ok(self.field(pointer)?.is_some_and(|field| {
+            field.format.as_deref() == Some("date")
+                && field.types.iter().any(|field_type| field_type == "string")
+        }))
+    }
+
+    /// Walks the instance alongside the schema, see `walk::visit_instance`.
+    pub(crate) fn visit<F>(
+        &self,
+        instance: &mut Value,
+        mode: BranchMode,
+        visitor: &mut F,
+    ) -> Result<()>
+    where
+        F: for<'r> FnMut(&SchemaWalker<'r>, &[SchemaNode<'r>], &mut Value, &str) -> Result<()>,
+    {
+        self.walk(|walker, root| visit_instance(walker, &[root], instance, "", mode, visitor))
+    }
+
+    /// Whether the instance is valid against the schema.
+    pub fn is_valid(&self, instance: &Value) -> Result<bool> {
+        Ok(self.validator()?.is_valid(instance))
+    }
+
+    /// Compiles a validator for the schema.
+    pub fn validator(&self) -> Result<Arc<Validator>> {
+        self.walk(|walker, root| {
+            walker
+                .validator(&root)
+                .ok_or_else(|| anyhow!("Invalid json schema"))
+        })
+    }
+
+    /// Calls the function with a walker over the schema and the node for its root.
+    pub(crate) fn walk<T, F>(&self, f: F) -> Result<T>
+    where
+        F: for<'r> FnOnce(&SchemaWalker<'r>, SchemaNode<'r>) -> Result<T>,
+    {
+        let walker = SchemaWalker::new(&self.registry);
+        let root = walker.root(&self.root_uri)?;
+
+        f(&walker, root)
+    }
+
+    /// Every schema applying to the field at the pointer, and whether the field is required.
+    fn resolve<'r>(
+        &'r self,
+        walker: &SchemaWalker<'r>,
+        pointer: &str,
+        instance: Option<&Value>,
+    ) -> Result<Option<(Vec<SchemaNode<'r>>, bool)>> {
+        let segments = match pointer {
+            "" => Vec::new(),
+            pointer => pointer
+                .strip_prefix('/')
+                .ok_or_else(|| anyhow!("Invalid json pointer \"{pointer}\""))?
+                .split('/')
+                .map(unescape_pointer_segment)
+                .collect(),
+        };
+
+        let mode = match instance {
+            Some(_) => BranchMode::Matching,
+            None => BranchMode::All,
+        };
+        let mut instance = instance;
+
+        let root = walker.root(&self.root_uri)?;
+        let mut nodes = walker.expand(&root, instance, mode)?;
+        let mut required = true;
+
+        for segment in segments {
+            let mut children = walker.property_nodes(&nodes, &segment)?;
+            required = nodes.iter().any(|node| {
+                !node.conditional
+                    && node
+                        .get("required")
+                        .and_then(Value::as_array)
+                        .is_some_and(|required| required.iter().any(|key| key == &segment))
+            });
+
+            if children.is_empty() {
+                if let Ok(index) = segment.parse::<usize>() {
+                    children = walker.item_nodes(&nodes, index)?;
+                    required = false;
+                }
+            }
+            if children.is_empty() {
+                return Ok(None);
+            }
+
+            instance = instance.and_then(|instance| match instance {
+                Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => instance.get(&segment),
+            });
+            nodes = walker.expand_all(&children, instance, mode)?;
+        }
+
+        Ok(Some((nodes, required)))
+    }
+}
+
+/// The types allowed by every unconditional node or, if none declare a type, by any conditional
+/// node.
+pub(crate) fn field_types(
+    unconditional: &[&SchemaNode],
+    conditional: &[&SchemaNode],
+) -> Vec<String> {
+    let mut types: Option<Vec<&str>> = None;
+    for node in unconditional
+        .iter()
+        .filter(|node| node.get("type").is_some())
+    {
+        let node_types = node.types();
+        types = Some(match types {
+            None => node_types,
+            Some(types) => types
+                .into_iter()
+                .filter(|field_type| node_types.contains(field_type))
+                .collect(),
+        });
+    }
+
+    let types = types.unwrap_or_else(|| {
+        let mut union = Vec::new();
+        for field_type in conditional.iter().flat_map(|node| node.types()) {
+            if !union.contains(&field_type) {
+                union.push(field_type);
+            }
+        }
+        union
+    });
+
+    types.into_iter().map(str::to_string).collect()
+}
+
+fn first_keyword<'r>(
+    unconditional: &[&SchemaNode<'r>],
+    conditional: &[&SchemaNode<'r>],
+    keyword: &str,
+) -> Option<&'r Value> {
+    unconditional
+        .iter()
+        .chain(conditional)
+        .find_map(|node| node.get(keyword))
+}
+
+/// The values allowed by `enum` or `const`, or `None` if any value of the field's types is allowed.
+pub(crate) fn enum_values(
+    unconditional: &[&SchemaNode],
+    conditional: &[&SchemaNode],
+) -> Option<Vec<Value>> {
+    let values_of = |node: &SchemaNode| match (node.get("enum"), node.get("const")) {
+        (Some(Value::Array(values)), _) => Some(values.clone()),
+        (_, Some(value)) => Some(vec![value.clone()]),
+        _ => None,
+    };
+
+    if let Some(values) = unconditional.iter().find_map(|node| values_of(node)) {
+        return Some(values);
+    }
+
+    // Across branches a field may take any value one of the branches allows, so the values are
+    // only known if every branch limits them.
+    let branch_values: Option<Vec<Vec<Value>>> = conditional
+        .iter()
+        .filter(|node| {
+            node.get("type").is_some() || node.get("enum").is_some() || node.get("const").is_some()
+        })
+        .map(|node| values_of(node))
+        .collect();
+    let mut union = Vec::new();
+    for value in branch_values?.into_iter().flatten() {
+        if !union.contains(&value) {
+            union.push(value);
+        }
+    }
+
+    (!union.is_empty()).then_some(union)
+}
+
+fn merge_schemas(nodes: &[&SchemaNode]) -> Value {
+    let mut merged = Map::new();
+
+    for node in nodes {
+        let Some(object) = node.schema.as_object() else {
+            continue;
+        };
+        for (keyword, value) in object {
+            if STRUCTURAL_KEYWORDS.contains(&keyword.as_str()) {
+                continue;
+            }
+            match (keyword.as_str(), merged.get_mut(keyword), value) {
+                ("properties", Some(Value::Object(merged)), Value::Object(properties)) => {
+                    for (property, schema) in properties {
+                        merged.entry(property).or_insert_with(|| schema.clone());
+                    }
+                }
+                ("required", Some(Value::Array(merged)), Value::Array(required)) => {
+                    for property in required {
+                        if !merged.contains(property) {
+                            merged.push(property.clone());
+                        }
+                    }
+                }
+                (_, Some(_), _) => {}
+                (_, None, _) => {
+                    merged.insert(keyword.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    Value::Object(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::SchemaInspector;
+    use crate::registry::SchemaRegistry;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "startDate": { "type": "string", "format": "date" },
+                "endDate": { "type": ["string", "null"], "format": "date" },
+                "status": { "$ref": "#/$defs/status" },
+                "address": { "$ref": "#/$defs/address" },
+                "contacts": {
+                    "type": "array",
+                    "items": {
+                        "allOf": [
+                            { "$ref": "#/$defs/contact" },
+                            { "required": ["preferred"] }
+                        ]
+                    }
+                },
+                "reference": {
+                    "oneOf": [
+                        { "type": "string", "pattern": "^[A-Z]+$" },
+                        { "type": "null" }
+                    ]
+                }
+            },
+            "required": ["startDate", "address"],
+            "$defs": {
+                "status": { "enum": ["OPEN", "CLOSED"], "default": "OPEN" },
+                "address": {
+                    "type": "object",
+                    "properties": {
+                        "postcode": { "type": "string", "maxLength": 8 },
+                        "movedIn": { "type": "string", "format": "date" }
+                    },
+                    "required": ["postcode"]
+                },
+                "contact": {
+                    "type": "object",
+                    "properties": {
+                        "email": { "type": "string", "format": "email" },
+                        "preferred": { "type": "boolean", "default": false }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn field_describes_top_level_fields() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let start_date = inspector.field("/startDate").unwrap().unwrap();
+        let end_date = inspector.field("/endDate").unwrap().unwrap();
+
+        assert_eq!(start_date.types, vec!["string"]);
+        assert_eq!(start_date.format.as_deref(), Some("date"));
+        assert!(start_date.required);
+        assert!(!start_date.nullable);
+        assert_eq!(end_date.types, vec!["string", "null"]);
+        assert!(end_date.nullable);
+        assert!(!end_date.required);
+    }
+
+    #[test]
+    fn field_follows_refs_into_nested_objects() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let postcode = inspector.field("/address/postcode").unwrap().unwrap();
+        let status = inspector.field("/status").unwrap().unwrap();
+
+        assert_eq!(postcode.types, vec!["string"]);
+        assert!(postcode.required);
+        assert_eq!(postcode.schema, json!({ "type": "string", "maxLength": 8 }));
+        assert_eq!(
+            status.enum_values,
+            Some(vec![json!("OPEN"), json!("CLOSED")])
+        );
+        assert_eq!(status.default, Some(json!("OPEN")));
+        assert!(inspector.is_date_field("/address/movedIn").unwrap());
+    }
+
+    #[test]
+    fn field_looks_through_array_items_and_all_of() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let contact = inspector.field("/contacts/3").unwrap().unwrap();
+        let preferred = inspector.field("/contacts/0/preferred").unwrap().unwrap();
+        let email = inspector.field("/contacts/0/email").unwrap().unwrap();
+
+        assert_eq!(contact.types, vec!["object"]);
+        assert_eq!(contact.schema["required"], json!(["preferred"]));
+        assert!(preferred.required);
+        assert_eq!(preferred.default, Some(json!(false)));
+        assert_eq!(email.format.as_deref(), Some("email"));
+        assert!(!email.required);
+    }
+
+    #[test]
+    fn field_combines_one_of_branches() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let reference = inspector.field("/reference").unwrap().unwrap();
+
+        assert_eq!(reference.types, vec!["string", "null"]);
+        assert!(reference.nullable);
+        assert_eq!(reference.enum_values, None);
+        assert_eq!(reference.schema, json!({}));
+    }
+
+    #[test]
+    fn field_for_instance_only_considers_matching_branches() {
+        let schema = json!({
+            "oneOf": [
+                {
+                    "properties": {
+                        "kind": { "const": "person" },
+                        "dateOfBirth": { "type": "string", "format": "date" }
+                    },
+                    "required": ["kind"]
+                },
+                {
+                    "properties": {
+                        "kind": { "const": "company" },
+                        "dateOfBirth": { "type": "string" }
+                    },
+                    "required": ["kind"]
+                }
+            ]
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+
+        let person = json!({ "kind": "person", "dateOfBirth": "2000-01-01" });
+        let company = json!({ "kind": "company", "dateOfBirth": "n/a" });
+
+        let for_person = inspector
+            .field_for_instance(&person, "/dateOfBirth")
+            .unwrap()
+            .unwrap();
+        let for_company = inspector
+            .field_for_instance(&company, "/dateOfBirth")
+            .unwrap()
+            .unwrap();
+        assert_eq!(for_person.format.as_deref(), Some("date"));
+        assert_eq!(
+            for_person.schema,
+            json!({ "type": "string", "format": "date" })
+        );
+        assert_eq!(for_company.format, None);
+        assert_eq!(
+            inspector.field("/kind").unwrap().unwrap().enum_values,
+            Some(vec![json!("person"), json!("company")])
+        );
+    }
+
+    #[test]
+    fn field_is_none_for_fields_the_schema_does_not_describe() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        assert_eq!(inspector.field("/unknown").unwrap(), None);
+        assert_eq!(inspector.field("/address/postcode/deeper").unwrap(), None);
+        assert!(!inspector.is_date_field("/unknown").unwrap());
+        assert!(inspector.field("no-leading-slash").is_err());
+    }
+
+    #[test]
+    fn field_resolves_refs_into_registered_schemas() {
+        let mut registry = SchemaRegistry::new();
+        registry
+            .register(
+                "money.json",
+                json!({
+                    "type": "object",
+                    "properties": { "currency": { "enum": ["GBP", "EUR"] } }
+                }),
+            )
+            .unwrap();
+        let schema = json!({ "properties": { "balance": { "$ref": "money.json" } } });
+
+        let inspector = SchemaInspector::with_registry(&schema, &registry).unwrap();
+        let currency = inspector.field("/balance/currency").unwrap().unwrap();
+
+        assert_eq!(currency.enum_values, Some(vec![json!("GBP"), json!("EUR")]));
+    }
+
+    #[test]
+    fn for_uri_resolves_relative_refs_from_the_schema_uri() {
+        let mut registry = SchemaRegistry::new();
+        registry
+            .register(
+                "common/postcode.json",
+                json!({ "type": "string", "maxLength": 8 }),
+            )
+            .unwrap();
+        registry
+            .register(
+                "common/address.json",
+                json!({ "properties": { "postcode": { "$ref": "postcode.json" } } }),
+            )
+            .unwrap();
+
+        let inspector = SchemaInspector::for_uri(&registry, "common/address.json").unwrap();
+        let postcode = inspector.field("/postcode").unwrap().unwrap();
+
+        assert_eq!(postcode.schema["maxLength"], json!(8));
+        assert!(SchemaInspector::for_uri(&registry, "missing.json").is_err());
+    }
+}
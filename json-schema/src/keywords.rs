@@ -0,0 +1,524 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, Months, NaiveTime, Utc};
+use jsonschema::paths::{LazyLocation, Location};
+use jsonschema::{Keyword, ValidationError, ValidationOptions, Validator};
+use serde_json::Value;
+
+use crate::dates::{parse_date_value, DateCoercionOptions, DateLocale, DateValue};
+use crate::report::ValidationReport;
+
+/// What a custom keyword validator is given to check a value.
+pub struct KeywordContext<'a> {
+    /// The value the keyword applies to. For a keyword in an object's schema, that's the whole
+    /// object, so the keyword can compare its fields.
+    pub value: &'a Value,
+    /// The keyword's value in the schema.
+    pub keyword_value: &'a Value,
+    /// The schema object the keyword appears in.
+    pub schema: &'a Value,
+}
+
+/// Why a value failed a custom keyword.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordError {
+    /// The property of the value that failed, for keywords that check an object's fields. The
+    /// failure is reported at the property rather than the object.
+    pub property: Option<String>,
+    pub message: String,
+}
+
+impl KeywordError {
+    /// A failure of one of the object's properties.
+    pub fn at(property: &str, message: impl Into<String>) -> Self {
+        KeywordError {
+            property: Some(property.to_string()),
+            message: message.into(),
+        }
+    }
+}
+
+impl From<String> for KeywordError {
+    fn from(message: String) -> Self {
+        KeywordError {
+            property: None,
+            message,
+        }
+    }
+}
+
+/// Validates values with a custom keyword.
+///
+/// Closures taking a `KeywordContext` are keyword validators that accept any keyword value.
+pub trait KeywordValidator: Send + Sync {
+    /// Checks the keyword's value when a schema using it is compiled, failing with a message
+    /// describing the problem.
+    fn check_schema(&self, _keyword_value: &Value) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Checks a value the keyword applies to.
+    fn validate(&self, context: &KeywordContext) -> Result<(), KeywordError>;
+}
+
+impl<F> KeywordValidator for F
+where
+    F: Fn(&KeywordContext) -> Result<(), KeywordError> + Send + Sync,
+{
+    fn validate(&self, context: &KeywordContext) -> Result<(), KeywordError> {
+        self(context)
+    }
+}
+
+/// Validators for keywords jsonschema doesn't know, such as our `x-` schema extensions.
+///
+/// Custom keywords are compiled into the jsonschema validator, so they apply wherever the schema
+/// they appear in does, including `anyOf`, `oneOf` and `not` branches, and fail like built-in
+/// keywords. A keyword comparing fields goes in their object's schema, where it sees the whole
+/// object. `KeywordRegistry::new` comes with:
+///
+/// * `x-minAge`: a date of birth at least this many whole years before today.
+/// * `x-afterField`: in an object's schema, maps date or date-time fields to the field each must
+///   be later than, when both are present.
+#[derive(Clone)]
+pub struct KeywordRegistry {
+    keywords: BTreeMap<String, Arc<dyn KeywordValidator>>,
+}
+
+impl Default for KeywordRegistry {
+    fn default() -> Self {
+        KeywordRegistry::new()
+    }
+}
+
+impl KeywordRegistry {
+    /// A registry with the Flexys keywords.
+    pub fn new() -> Self {
+        KeywordRegistry::empty()
+            .with_keyword("x-minAge", MinAge)
+            .with_keyword("x-afterField", AfterField)
+    }
+
+    /// A registry with no custom keywords.
+    pub fn empty() -> Self {
+        KeywordRegistry {
+            keywords: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a keyword validator, replacing any validator already registered for the keyword.
+    pub fn with_keyword<K>(mut self, keyword: &str, validator: K) -> Self
+    where
+        K: KeywordValidator + 'static,
+    {
+        self.keywords
+            .insert(keyword.to_string(), Arc::new(validator));
+        self
+    }
+
+    /// The registered keywords.
+    pub fn keywords(&self) -> Vec<&str> {
+        self.keywords.keys().map(String::as_str).collect()
+    }
+
+    /// Adds the registered keywords to the validation options.
+    // The keyword factories' error type is jsonschema's.
+    #[allow(clippy::result_large_err)]
+    pub fn configure(&self, mut options: ValidationOptions) -> ValidationOptions {
+        for (name, validator) in &self.keywords {
+            let validator = Arc::clone(validator);
+            options = options.with_keyword(name.as_str(), move |parent, value, location| {
+                if let Err(message) = validator.check_schema(value) {
+                    return Err(ValidationError::custom(
+                        location,
+                        Location::new(),
+                        value,
+                        message,
+                    ));
+                }
+                Ok(Box::new(CustomKeyword {
+                    schema: Value::Object(parent.clone()),
+                    keyword_value: value.clone(),
+                    location,
+                    validator: Arc::clone(&validator),
+                }) as Box<dyn Keyword>)
+            });
+        }
+
+        options
+    }
+
+    /// Compiles a validator for the schema that checks the registered keywords, failing if a
+    /// keyword's value is malformed.
+    pub fn validator_for(&self, schema: &Value) -> Result<Validator> {
+        self.configure(jsonschema::options())
+            .build(schema)
+            .map_err(|err| anyhow!("Invalid json schema, error: {err}"))
+    }
+
+    /// Validates the instance against the schema, reporting the custom keywords' failures like
+    /// jsonschema's own errors, with the custom keyword as `keyword`.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema`: The JSON schema to validate against.
+    /// * `instance`: The JSON value to validate.
+    /// * `max_errors`: The maximum number of issues to collect, or `None` to collect them all.
+    ///
+    pub fn validate(
+        &self,
+        schema: &Value,
+        instance: &Value,
+        max_errors: Option<usize>,
+    ) -> Result<ValidationReport> {
+        let validator = self.validator_for(schema)?;
+
+        Ok(ValidationReport::collect(&validator, instance, max_errors))
+    }
+}
+
+/// A registered keyword where it appears in a compiled schema.
+struct CustomKeyword {
+    schema: Value,
+    keyword_value: Value,
+    location: Location,
+    validator: Arc<dyn KeywordValidator>,
+}
+
+impl CustomKeyword {
+    fn check(&self, value: &Value) -> Result<(), KeywordError> {
+        self.validator.validate(&KeywordContext {
+            value,
+            keyword_value: &self.keyword_value,
+            schema: &self.schema,
+        })
+    }
+}
+
+impl Keyword for CustomKeyword {
+    fn validate<'i>(
+        &self,
+        instance: &'i Value,
+        location: &LazyLocation,
+    ) -> Result<(), ValidationError<'i>> {
+        self.check(instance).map_err(|error| {
+            let instance_path = Location::from(location);
+            let failed = error
+                .property
+                .as_ref()
+                .and_then(|property| Some((property, instance.get(property)?)));
+            match failed {
+                Some((property, value)) => ValidationError::custom(
+                    self.location.clone(),
+                    instance_path.join(property),
+                    value,
+                    error.message,
+                ),
+                None => ValidationError::custom(
+                    self.location.clone(),
+                    instance_path,
+                    instance,
+                    error.message,
+                ),
+            }
+        })
+    }
+
+    fn is_valid(&self, instance: &Value) -> bool {
+        self.check(instance).is_ok()
+    }
+}
+
+/// `x-minAge`: a date of birth at least this many whole years before today.
+struct MinAge;
+
+impl KeywordValidator for MinAge {
+    fn check_schema(&self, keyword_value: &Value) -> Result<(), String> {
+        match keyword_value.as_u64() {
+            Some(_) => Ok(()),
+            None => Err(format!(
+                "x-minAge must be a whole number of years, not {keyword_value}"
+            )),
+        }
+    }
+
+    fn validate(&self, context: &KeywordContext) -> Result<(), KeywordError> {
+        let years = context.keyword_value.as_u64().unwrap_or_default();
+        let Some(born) = comparable_date(context.value, context.schema) else {
+            return Ok(());
+        };
+
+        let today = Utc::now().fixed_offset();
+        let adult = years
+            .checked_mul(12)
+            .and_then(|months| u32::try_from(months).ok())
+            .and_then(|months| born.checked_add_months(Months::new(months)))
+            .is_some_and(|birthday| birthday <= today);
+
+        if adult {
+            Ok(())
+        } else {
+            Err(format!("{} is less than {years} years ago", context.value).into())
+        }
+    }
+}
+
+/// `x-afterField`: date fields of an object that must be later than other fields.
+struct AfterField;
+
+impl KeywordValidator for AfterField {
+    fn check_schema(&self, keyword_value: &Value) -> Result<(), String> {
+        match keyword_value.as_object() {
+            Some(fields) if fields.values().all(Value::is_string) => Ok(()),
+            _ => Err(format!(
+                "x-afterField must map field names to the field each is after, not {keyword_value}"
+            )),
+        }
+    }
+
+    fn validate(&self, context: &KeywordContext) -> Result<(), KeywordError> {
+        let (Value::Object(object), Value::Object(fields)) = (context.value, context.keyword_value)
+        else {
+            return Ok(());
+        };
+        let field_schema = |field: &str| {
+            context
+                .schema
+                .get("properties")
+                .and_then(|properties| properties.get(field))
+                .unwrap_or(&Value::Null)
+        };
+
+        for (field, earlier) in fields {
+            let Some(earlier) = earlier.as_str() else {
+                continue;
+            };
+            let (Some(value), Some(other)) = (object.get(field), object.get(earlier)) else {
+                continue;
+            };
+            let (Some(date), Some(other_date)) = (
+                comparable_date(value, field_schema(field)),
+                comparable_date(other, field_schema(earlier)),
+            ) else {
+                continue;
+            };
+
+            if date <= other_date {
+                return Err(KeywordError::at(
+                    field,
+                    format!("{value} is not after {earlier} ({other})"),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a date or date-time so they can be compared, taking dates as midnight UTC. Values that
+/// aren't dates are left for `format` validation to report.
+fn comparable_date(value: &Value, schema: &Value) -> Option<DateTime<FixedOffset>> {
+    let options = DateCoercionOptions {
+        locale: DateLocale::Iso,
+        accept_epoch_millis: false,
+    };
+    let formats: &[&str] = match schema.get("format").and_then(Value::as_str) {
+        Some("date-time") => &["date-time"],
+        _ => &["date", "date-time"],
+    };
+
+    formats
+        .iter()
+        .find_map(|candidate| parse_date_value(candidate, value, &options).ok())
+        .and_then(|date| match date {
+            DateValue::Date(date) => Some(date.and_time(NaiveTime::MIN).and_utc().fixed_offset()),
+            DateValue::DateTime(date_time) => Some(date_time),
+            DateValue::Time(..) => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{KeywordContext, KeywordError, KeywordRegistry};
+    use crate::report::{ValidationIssue, ValidationReport};
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "dateOfBirth": { "type": "string", "format": "date", "x-minAge": 18 },
+                "arrangement": { "$ref": "#/$defs/arrangement" }
+            },
+            "$defs": {
+                "arrangement": {
+                    "type": "object",
+                    "properties": {
+                        "startDate": { "type": "string", "format": "date" },
+                        "endDate": { "type": "string", "format": "date" }
+                    },
+                    "x-afterField": { "endDate": "startDate" }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn validate_passes_instances_the_keywords_accept() {
+        let instance = json!({
+            "dateOfBirth": "1980-06-15",
+            "arrangement": { "startDate": "2025-01-01", "endDate": "2025-06-30" }
+        });
+
+        let report = KeywordRegistry::new()
+            .validate(&schema(), &instance, None)
+            .unwrap();
+
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn validate_reports_keyword_failures_like_built_in_errors() {
+        let instance = json!({
+            "dateOfBirth": "2100-01-01",
+            "arrangement": { "startDate": "2025-01-01", "endDate": "2024-12-31" }
+        });
+
+        let report = KeywordRegistry::new()
+            .validate(&schema(), &instance, None)
+            .unwrap();
+
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue {
+                    instance_path: "/arrangement/endDate".to_string(),
+                    schema_path: "/properties/arrangement/$ref/x-afterField".to_string(),
+                    keyword: "x-afterField".to_string(),
+                    message: "\"2024-12-31\" is not after startDate (\"2025-01-01\")".to_string(),
+                    value: json!("2024-12-31"),
+                },
+                ValidationIssue {
+                    instance_path: "/dateOfBirth".to_string(),
+                    schema_path: "/properties/dateOfBirth/x-minAge".to_string(),
+                    keyword: "x-minAge".to_string(),
+                    message: "\"2100-01-01\" is less than 18 years ago".to_string(),
+                    value: json!("2100-01-01"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_reports_custom_and_built_in_keywords_together() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string", "x-prefix": "CASE-" }
+            },
+            "required": ["owner"]
+        });
+        let keywords = KeywordRegistry::empty().with_keyword(
+            "x-prefix",
+            |context: &KeywordContext| -> Result<(), KeywordError> {
+                let prefix = context.keyword_value.as_str().unwrap_or_default();
+                if context
+                    .value
+                    .as_str()
+                    .is_some_and(|value| value.starts_with(prefix))
+                {
+                    Ok(())
+                } else {
+                    Err(format!("{} does not start with {prefix}", context.value).into())
+                }
+            },
+        );
+
+        let report = keywords
+            .validate(&schema, &json!({ "reference": "1234" }), None)
+            .unwrap();
+        let keywords: Vec<&str> = report
+            .issues
+            .iter()
+            .map(|issue| issue.keyword.as_str())
+            .collect();
+
+        assert_eq!(keywords, vec!["x-prefix", "required"]);
+        assert_eq!(
+            report.to_string(),
+            "Json failed validation, error(s): Validation Error [\"1234\" does not start with \
+             CASE-]. Schema Path [/properties/reference/x-prefix]. Instance Path [/reference]., \
+             Validation Error [\"owner\" is a required property]. Schema Path [/required]. \
+             Instance Path []."
+        );
+    }
+
+    #[test]
+    fn validate_stops_at_the_error_cap() {
+        let instance = json!({
+            "dateOfBirth": "2100-01-01",
+            "arrangement": { "startDate": "2025-01-01", "endDate": "2024-12-31" }
+        });
+
+        let report = KeywordRegistry::new()
+            .validate(&schema(), &instance, Some(1))
+            .unwrap();
+
+        assert_eq!(report.issues.len(), 1);
+        assert!(report.truncated);
+    }
+
+    #[test]
+    fn validator_for_applies_keywords_through_any_validator_api() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "dateOfBirth": {
+                    "anyOf": [
+                        { "type": "null" },
+                        { "type": "string", "format": "date", "x-minAge": 18 }
+                    ]
+                },
+                "correction": { "not": { "x-afterField": { "endDate": "startDate" } } }
+            }
+        });
+        let validator = KeywordRegistry::new().validator_for(&schema).unwrap();
+
+        assert!(!validator.is_valid(&json!({ "dateOfBirth": "2100-01-01" })));
+        assert!(validator.is_valid(&json!({ "dateOfBirth": null })));
+        let backwards =
+            json!({ "correction": { "startDate": "2025-01-01", "endDate": "2024-12-31" } });
+        assert!(validator.is_valid(&backwards));
+        assert!(validator.validate(&backwards).is_ok());
+        let forwards =
+            json!({ "correction": { "startDate": "2025-01-01", "endDate": "2025-06-30" } });
+        let report = ValidationReport::collect(&validator, &forwards, None);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].keyword, "not");
+    }
+
+    #[test]
+    fn validator_for_rejects_malformed_keyword_values() {
+        let keywords = KeywordRegistry::new();
+
+        let min_age = keywords
+            .validator_for(&json!({ "x-minAge": "18" }))
+            .unwrap_err();
+        let after_field = keywords
+            .validator_for(&json!({ "x-afterField": "startDate" }))
+            .unwrap_err();
+
+        assert_eq!(
+            min_age.to_string(),
+            "Invalid json schema, error: x-minAge must be a whole number of years, not \"18\""
+        );
+        assert_eq!(
+            after_field.to_string(),
+            "Invalid json schema, error: x-afterField must map field names to the field each is \
+             after, not \"startDate\""
+        );
+    }
+}
@@ -1,2 +1,28 @@
+#[cfg(feature = "actix")]
+pub mod actix;
+pub mod batch;
+pub mod bundle;
+pub mod canonical;
+pub mod coerce;
+pub mod compatibility;
+pub mod dates;
+pub mod defaults;
+#[cfg(feature = "derive")]
+pub mod derive;
+pub mod diff;
+pub mod explain;
+pub mod formats;
+pub mod generate;
+pub mod inspector;
+pub mod keywords;
+pub mod limits;
+pub mod lint;
+pub mod patch;
+pub mod prune;
+pub mod redact;
+pub mod registry;
+pub mod report;
+pub mod typed;
 pub mod util;
 pub mod validation;
+mod walk;
@@ -0,0 +1,474 @@
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use jsonschema::{PatternOptions, Validator};
+use serde_json::Value;
+
+use crate::registry::{DATA_KEYWORDS, SCHEMA_MAP_KEYWORDS};
+use crate::util::escape_pointer_segment;
+
+/// Bounds on the size and shape of instances and schemas, protecting validation from hostile
+/// payloads. `None` leaves that aspect unlimited.
+///
+/// A validation that times out can't be stopped: its thread carries on in the background, holding
+/// a copy of the instance, until it finishes. `max_timed_validations` bounds how many of these
+/// threads there can be, and the size and regex limits bound how long each runs, but until then
+/// each still uses a CPU and memory for its copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationLimits {
+    /// The deepest nesting of objects and arrays allowed, the root being at depth 0.
+    pub max_depth: Option<usize>,
+    /// The most characters allowed in a string value or object key.
+    pub max_string_length: Option<usize>,
+    /// The most items allowed in an array.
+    pub max_array_length: Option<usize>,
+    /// The most values allowed in the whole instance, counting every object, array and scalar.
+    pub max_nodes: Option<usize>,
+    /// Guards against the schema's `pattern` and `patternProperties` regexes.
+    pub regex: Option<RegexLimits>,
+    /// How long validation may run for.
+    pub timeout: Option<Duration>,
+    /// The most validations with a timeout that may run at once across the process, counting
+    /// those still running after timing out. Beyond it, validation fails straight away.
+    pub max_timed_validations: Option<usize>,
+}
+
+impl Default for ValidationLimits {
+    fn default() -> Self {
+        ValidationLimits {
+            max_depth: Some(64),
+            max_string_length: Some(1024 * 1024),
+            max_array_length: Some(100_000),
+            max_nodes: Some(1_000_000),
+            regex: Some(RegexLimits::default()),
+            timeout: Some(Duration::from_secs(5)),
+            max_timed_validations: Some(16),
+        }
+    }
+}
+
+impl ValidationLimits {
+    /// No limits at all.
+    pub fn unlimited() -> Self {
+        ValidationLimits {
+            max_depth: None,
+            max_string_length: None,
+            max_array_length: None,
+            max_nodes: None,
+            regex: None,
+            timeout: None,
+            max_timed_validations: None,
+        }
+    }
+}
+
+/// Guards against regexes prone to catastrophic backtracking.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexLimits {
+    /// The most characters allowed in a pattern.
+    pub max_length: usize,
+    /// Reject patterns that repeat a group which itself contains a repetition, such as `(a+)+`,
+    /// the usual cause of exponential backtracking.
+    pub reject_nested_quantifiers: bool,
+    /// How many times the regex engine may backtrack while matching a value, after which the
+    /// match fails.
+    pub backtrack_limit: usize,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        RegexLimits {
+            max_length: 1000,
+            reject_nested_quantifiers: true,
+            backtrack_limit: 100_000,
+        }
+    }
+}
+
+/// Which limit was exceeded, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitExceeded {
+    Depth {
+        /// JSON pointer to the first value nested too deeply.
+        instance_path: String,
+        limit: usize,
+    },
+    StringLength {
+        /// JSON pointer to the string, or the object holding the key.
+        instance_path: String,
+        length: usize,
+        limit: usize,
+    },
+    ArrayLength {
+        instance_path: String,
+        length: usize,
+        limit: usize,
+    },
+    Nodes {
+        limit: usize,
+    },
+    Regex {
+        /// JSON pointer to the `pattern` keyword or `patternProperties` entry.
+        schema_path: String,
+        pattern: String,
+        reason: String,
+    },
+    Timeout {
+        limit: Duration,
+    },
+    /// Too many validations with a timeout were already running.
+    Busy {
+        limit: usize,
+    },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Depth {
+                instance_path,
+                limit,
+            } => write!(
+                f,
+                "Json exceeded the maximum depth of {limit}. Instance Path [{instance_path}]."
+            ),
+            LimitExceeded::StringLength {
+                instance_path,
+                length,
+                limit,
+            } => write!(
+                f,
+                "Json string of {length} characters exceeded the maximum length of {limit}. \
+                 Instance Path [{instance_path}]."
+            ),
+            LimitExceeded::ArrayLength {
+                instance_path,
+                length,
+                limit,
+            } => write!(
+                f,
+                "Json array of {length} items exceeded the maximum length of {limit}. \
+                 Instance Path [{instance_path}]."
+            ),
+            LimitExceeded::Nodes { limit } => {
+                write!(f, "Json exceeded the maximum of {limit} values.")
+            }
+            LimitExceeded::Regex {
+                schema_path,
+                pattern,
+                reason,
+            } => write!(
+                f,
+                "Json schema pattern \"{pattern}\" rejected, {reason}. Schema Path [{schema_path}]."
+            ),
+            LimitExceeded::Timeout { limit } => {
+                write!(f, "Json validation timed out after {limit:?}.")
+            }
+            LimitExceeded::Busy { limit } => {
+                write!(
+                    f,
+                    "Json validation refused, {limit} validations are already running."
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Checks the instance against the depth, string, array and node limits, stopping at the first
+/// one exceeded.
+pub fn check_instance(instance: &Value, limits: &ValidationLimits) -> Result<(), LimitExceeded> {
+    let mut nodes = 0;
+    let mut pending = vec![(instance, String::new(), 0)];
+
+    while let Some((value, path, depth)) = pending.pop() {
+        nodes += 1;
+        if let Some(limit) = limits.max_nodes.filter(|limit| nodes > *limit) {
+            return Err(LimitExceeded::Nodes { limit });
+        }
+        if let Some(limit) = limits.max_depth.filter(|limit| depth > *limit) {
+            return Err(LimitExceeded::Depth {
+                instance_path: path,
+                limit,
+            });
+        }
+
+        match value {
+            Value::String(string) => check_string_length(string, &path, limits)?,
+            Value::Array(items) => {
+                if let Some(limit) = limits.max_array_length.filter(|limit| items.len() > *limit) {
+                    return Err(LimitExceeded::ArrayLength {
+                        instance_path: path,
+                        length: items.len(),
+                        limit,
+                    });
+                }
+                for (index, item) in items.iter().enumerate().rev() {
+                    pending.push((item, format!("{path}/{index}"), depth + 1));
+                }
+            }
+            Value::Object(object) => {
+                for (key, item) in object.iter().rev() {
+                    check_string_length(key, &path, limits)?;
+                    let item_path = format!("{path}/{}", escape_pointer_segment(key));
+                    pending.push((item, item_path, depth + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn check_string_length(
+    string: &str,
+    path: &str,
+    limits: &ValidationLimits,
+) -> Result<(), LimitExceeded> {
+    if let Some(limit) = limits.max_string_length {
+        // Only count the characters of strings that could be too long.
+        if string.len() > limit && string.chars().count() > limit {
+            return Err(LimitExceeded::StringLength {
+                instance_path: path.to_string(),
+                length: string.chars().count(),
+                limit,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks every `pattern` and `patternProperties` regex in the schema against the regex limits.
+pub fn check_schema(schema: &Value, limits: &ValidationLimits) -> Result<(), LimitExceeded> {
+    match &limits.regex {
+        Some(regex) => check_patterns(schema, "", regex),
+        None => Ok(()),
+    }
+}
+
+fn check_patterns(schema: &Value, path: &str, limits: &RegexLimits) -> Result<(), LimitExceeded> {
+    match schema {
+        Value::Object(object) => {
+            for (keyword, value) in object {
+                if DATA_KEYWORDS.contains(&keyword.as_str()) {
+                    continue;
+                }
+                let keyword_path = format!("{path}/{}", escape_pointer_segment(keyword));
+                match (keyword.as_str(), value) {
+                    ("pattern", Value::String(pattern)) => {
+                        check_pattern(pattern, &keyword_path, limits)?
+                    }
+                    ("patternProperties", Value::Object(patterns)) => {
+                        for pattern in patterns.keys() {
+                            let pattern_path =
+                                format!("{keyword_path}/{}", escape_pointer_segment(pattern));
+                            check_pattern(pattern, &pattern_path, limits)?;
+                        }
+                    }
+                    _ => {}
+                }
+                match value {
+                    // The keys are names, so are never keywords.
+                    Value::Object(schemas) if SCHEMA_MAP_KEYWORDS.contains(&keyword.as_str()) => {
+                        for (name, schema) in schemas {
+                            let schema_path =
+                                format!("{keyword_path}/{}", escape_pointer_segment(name));
+                            check_patterns(schema, &schema_path, limits)?;
+                        }
+                    }
+                    _ => check_patterns(value, &keyword_path, limits)?,
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                check_patterns(item, &format!("{path}/{index}"), limits)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn check_pattern(pattern: &str, path: &str, limits: &RegexLimits) -> Result<(), LimitExceeded> {
+    let reason = if pattern.chars().count() > limits.max_length {
+        Some(format!(
+            "it is longer than {} characters",
+            limits.max_length
+        ))
+    } else if limits.reject_nested_quantifiers && has_nested_quantifier(pattern) {
+        Some("it repeats a group containing a repetition".to_string())
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(LimitExceeded::Regex {
+            schema_path: path.to_string(),
+            pattern: pattern.to_string(),
+            reason,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Whether the pattern quantifies a group that itself contains a quantifier, like `(a+)+` or
+/// `(\w*,)*`. Escapes and character classes are skipped, and `?` isn't treated as a repetition.
+fn has_nested_quantifier(pattern: &str) -> bool {
+    let mut chars = pattern.chars().peekable();
+    // Whether each open group contains a repetition so far.
+    let mut groups: Vec<bool> = Vec::new();
+    let mut in_class = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            ']' if in_class => in_class = false,
+            _ if in_class => {}
+            '[' => in_class = true,
+            '(' => groups.push(false),
+            ')' => {
+                let repeated_inside = groups.pop().unwrap_or(false);
+                let repeated = matches!(chars.peek(), Some('*' | '+' | '{'));
+                if repeated_inside && repeated {
+                    return true;
+                }
+                if let Some(outer) = groups.last_mut() {
+                    *outer |= repeated_inside || repeated;
+                }
+            }
+            '*' | '+' | '{' => {
+                if let Some(group) = groups.last_mut() {
+                    *group = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Compiles a validator for the schema after checking its regexes, with the regex engine's
+/// backtracking capped by the regex limits.
+pub fn validator_with_limits(schema: &Value, limits: &ValidationLimits) -> Result<Validator> {
+    check_schema(schema, limits)?;
+
+    let mut options = jsonschema::options();
+    if let Some(regex) = &limits.regex {
+        options = options.with_pattern_options(
+            PatternOptions::fancy_regex().backtrack_limit(regex.backtrack_limit),
+        );
+    }
+
+    options
+        .build(schema)
+        .map_err(|err| anyhow!("Invalid json schema, error: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        check_instance, check_schema, has_nested_quantifier, LimitExceeded, ValidationLimits,
+    };
+
+    #[test]
+    fn check_instance_reports_each_limit_distinctly() {
+        let limits = ValidationLimits {
+            max_depth: Some(2),
+            max_string_length: Some(5),
+            max_array_length: Some(3),
+            max_nodes: Some(10),
+            ..ValidationLimits::unlimited()
+        };
+
+        assert_eq!(
+            check_instance(&json!({ "a": { "b": { "c": 1 } } }), &limits),
+            Err(LimitExceeded::Depth {
+                instance_path: "/a/b/c".to_string(),
+                limit: 2
+            })
+        );
+        assert_eq!(
+            check_instance(&json!({ "name": "Jane Doe" }), &limits),
+            Err(LimitExceeded::StringLength {
+                instance_path: "/name".to_string(),
+                length: 8,
+                limit: 5
+            })
+        );
+        assert_eq!(
+            check_instance(&json!({ "items": [1, 2, 3, 4] }), &limits),
+            Err(LimitExceeded::ArrayLength {
+                instance_path: "/items".to_string(),
+                length: 4,
+                limit: 3
+            })
+        );
+        assert_eq!(
+            check_instance(&json!([[1, 2, 3], [4, 5, 6], [7, 8, 9]]), &limits),
+            Err(LimitExceeded::Nodes { limit: 10 })
+        );
+        assert_eq!(check_instance(&json!({ "a": [1, "two"] }), &limits), Ok(()));
+    }
+
+    #[test]
+    fn check_schema_rejects_catastrophic_patterns() {
+        let schema = json!({
+            "properties": {
+                "code": { "type": "string", "pattern": "^[A-Z]{3}-\\d+$" },
+                "tags": { "patternProperties": { "^(a+)+$": {} } }
+            }
+        });
+
+        let err = check_schema(&schema, &ValidationLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Json schema pattern \"^(a+)+$\" rejected, it repeats a group containing a \
+             repetition. Schema Path [/properties/tags/patternProperties/^(a+)+$]."
+        );
+        assert!(check_schema(&schema, &ValidationLimits::unlimited()).is_ok());
+    }
+
+    #[test]
+    fn check_schema_ignores_patterns_in_instance_data() {
+        let schema = json!({
+            "properties": {
+                "filter": {
+                    "enum": [{ "pattern": "^(a+)+$" }],
+                    "default": { "pattern": "^(a+)+$" },
+                    "examples": [{ "pattern": "^(a+)+$" }]
+                },
+                "enum": { "type": "string", "pattern": "^(b+)+$" }
+            }
+        });
+
+        let err = check_schema(&schema, &ValidationLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Json schema pattern \"^(b+)+$\" rejected, it repeats a group containing a \
+             repetition. Schema Path [/properties/enum/pattern]."
+        );
+    }
+
+    #[test]
+    fn has_nested_quantifier_spots_repeated_repetitions() {
+        assert!(has_nested_quantifier("(a+)+"));
+        assert!(has_nested_quantifier("^(\\w*,)*$"));
+        assert!(has_nested_quantifier("((ab)*c)+"));
+        assert!(!has_nested_quantifier("^(ab)+$"));
+        assert!(!has_nested_quantifier("^[(a+)]+$"));
+        assert!(!has_nested_quantifier("(\\d+)?-x"));
+    }
+}
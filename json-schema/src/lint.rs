@@ -0,0 +1,641 @@
+use std::fmt;
+
+use fancy_regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::registry::{DATA_KEYWORDS, SCHEMA_MAP_KEYWORDS};
+use crate::util::escape_pointer_segment;
+use crate::walk::pattern_matches;
+
+/// Every keyword of draft 4 through draft 2020-12, plus the OpenAPI `nullable` the inspector
+/// understands. Keywords starting `x-` are our own extensions and are never reported.
+const KNOWN_KEYWORDS: [&str; 64] = [
+    "$anchor",
+    "$comment",
+    "$defs",
+    "$dynamicAnchor",
+    "$dynamicRef",
+    "$id",
+    "$recursiveAnchor",
+    "$recursiveRef",
+    "$ref",
+    "$schema",
+    "$vocabulary",
+    "additionalItems",
+    "additionalProperties",
+    "allOf",
+    "anyOf",
+    "const",
+    "contains",
+    "contentEncoding",
+    "contentMediaType",
+    "contentSchema",
+    "default",
+    "definitions",
+    "dependencies",
+    "dependentRequired",
+    "dependentSchemas",
+    "deprecated",
+    "description",
+    "else",
+    "enum",
+    "examples",
+    "exclusiveMaximum",
+    "exclusiveMinimum",
+    "format",
+    "id",
+    "if",
+    "items",
+    "maxContains",
+    "maxItems",
+    "maxLength",
+    "maxProperties",
+    "maximum",
+    "minContains",
+    "minItems",
+    "minLength",
+    "minProperties",
+    "minimum",
+    "multipleOf",
+    "not",
+    "nullable",
+    "oneOf",
+    "pattern",
+    "patternProperties",
+    "prefixItems",
+    "properties",
+    "propertyNames",
+    "readOnly",
+    "required",
+    "then",
+    "title",
+    "type",
+    "unevaluatedItems",
+    "unevaluatedProperties",
+    "uniqueItems",
+    "writeOnly",
+];
+
+/// Keywords whose value is a single sub-schema.
+const SCHEMA_KEYWORDS: [&str; 12] = [
+    "additionalItems",
+    "additionalProperties",
+    "contains",
+    "contentSchema",
+    "else",
+    "if",
+    "items",
+    "not",
+    "propertyNames",
+    "then",
+    "unevaluatedItems",
+    "unevaluatedProperties",
+];
+
+/// Keywords whose value is an array of sub-schemas.
+const SCHEMA_ARRAY_KEYWORDS: [&str; 4] = ["allOf", "anyOf", "oneOf", "prefixItems"];
+
+/// Pairs of lower and upper bounds that contradict each other when the lower is the larger.
+const BOUNDS: [(&str, &str); 5] = [
+    ("minLength", "maxLength"),
+    ("minItems", "maxItems"),
+    ("minProperties", "maxProperties"),
+    ("minContains", "maxContains"),
+    ("minimum", "maximum"),
+];
+
+/// How serious a lint finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The schema doesn't do what its author meant, or can never be satisfied.
+    Error,
+    /// The schema is probably wrong, but may be intended.
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+/// The check that produced a lint finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintRule {
+    /// A keyword no draft defines, which validators silently ignore.
+    UnknownKeyword,
+    /// A `format` on a schema that doesn't say it's a string.
+    FormatWithoutType,
+    /// A `pattern` or `patternProperties` key that isn't a valid regular expression.
+    InvalidRegex,
+    /// Constraints that no value can satisfy.
+    Unsatisfiable,
+    /// A `required` property that `properties` doesn't describe.
+    RequiredNotInProperties,
+    /// A `oneOf` branch that can never be the one branch an instance matches.
+    UnreachableBranch,
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LintRule::UnknownKeyword => "unknown-keyword",
+            LintRule::FormatWithoutType => "format-without-type",
+            LintRule::InvalidRegex => "invalid-regex",
+            LintRule::Unsatisfiable => "unsatisfiable",
+            LintRule::RequiredNotInProperties => "required-not-in-properties",
+            LintRule::UnreachableBranch => "unreachable-branch",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A likely mistake found in a schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LintFinding {
+    /// JSON pointer to the offending keyword within the schema.
+    pub schema_path: String,
+    pub rule: LintRule,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] at [{}]: {}",
+            self.severity, self.rule, self.schema_path, self.message
+        )
+    }
+}
+
+/// Checks a hand-written schema for mistakes that validators accept without complaint, such as
+/// misspelt keywords, so would otherwise only show up as validation that never happens.
+///
+/// Only the schema document itself is checked: `$ref`s are not followed, but `$defs` are linted
+/// where they are defined.
+pub fn lint_schema(schema: &Value) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    lint(schema, "", &mut findings);
+
+    findings
+}
+
+fn lint(schema: &Value, path: &str, findings: &mut Vec<LintFinding>) {
+    let Value::Object(object) = schema else {
+        return;
+    };
+    let mut report = |segments: &[&str], rule, severity, message: String| {
+        let mut schema_path = path.to_string();
+        for segment in segments {
+            schema_path.push('/');
+            schema_path.push_str(&escape_pointer_segment(segment));
+        }
+        findings.push(LintFinding {
+            schema_path,
+            rule,
+            severity,
+            message,
+        });
+    };
+
+    for keyword in object.keys() {
+        if KNOWN_KEYWORDS.contains(&keyword.as_str()) || keyword.starts_with("x-") {
+            continue;
+        }
+        match closest_keyword(keyword) {
+            Some(known) => report(
+                &[keyword],
+                LintRule::UnknownKeyword,
+                Severity::Error,
+                format!("Unknown keyword \"{keyword}\", did you mean \"{known}\"?"),
+            ),
+            None => report(
+                &[keyword],
+                LintRule::UnknownKeyword,
+                Severity::Warning,
+                format!("Unknown keyword \"{keyword}\" is ignored by validators"),
+            ),
+        }
+    }
+
+    if object.contains_key("format")
+        && !["type", "$ref", "allOf", "anyOf", "oneOf", "const", "enum"]
+            .iter()
+            .any(|keyword| object.contains_key(*keyword))
+    {
+        report(
+            &["format"],
+            LintRule::FormatWithoutType,
+            Severity::Warning,
+            "\"format\" only applies to strings, but the schema allows any type".to_string(),
+        );
+    }
+
+    if let Some(pattern) = object.get("pattern").and_then(Value::as_str) {
+        if let Err(err) = Regex::new(pattern) {
+            report(
+                &["pattern"],
+                LintRule::InvalidRegex,
+                Severity::Error,
+                format!("Invalid regular expression \"{pattern}\": {err}"),
+            );
+        }
+    }
+    if let Some(Value::Object(patterns)) = object.get("patternProperties") {
+        for pattern in patterns.keys() {
+            if let Err(err) = Regex::new(pattern) {
+                report(
+                    &["patternProperties"],
+                    LintRule::InvalidRegex,
+                    Severity::Error,
+                    format!("Invalid regular expression \"{pattern}\": {err}"),
+                );
+            }
+        }
+    }
+
+    for (lower, upper) in BOUNDS {
+        let (Some(min), Some(max)) = (
+            object.get(lower).and_then(Value::as_f64),
+            object.get(upper).and_then(Value::as_f64),
+        ) else {
+            continue;
+        };
+        if min > max {
+            report(
+                &[lower],
+                LintRule::Unsatisfiable,
+                Severity::Error,
+                format!("\"{lower}\" of {min} is greater than \"{upper}\" of {max}"),
+            );
+        }
+    }
+    if let Some(Value::Array(values)) = object.get("enum") {
+        if values.is_empty() {
+            report(
+                &["enum"],
+                LintRule::Unsatisfiable,
+                Severity::Error,
+                "\"enum\" is empty, so no value is allowed".to_string(),
+            );
+        }
+    }
+    let types = types_of(object);
+    if !types.is_empty() {
+        let allowed: Vec<&Value> = match (object.get("const"), object.get("enum")) {
+            (Some(value), _) => vec![value],
+            (None, Some(Value::Array(values))) => values.iter().collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|value| has_any_type(value, &types)) {
+            let keyword = if object.contains_key("const") {
+                "const"
+            } else {
+                "enum"
+            };
+            report(
+                &[keyword],
+                LintRule::Unsatisfiable,
+                Severity::Error,
+                format!(
+                    "No value allowed by \"{keyword}\" is of type {}",
+                    types.join(" or ")
+                ),
+            );
+        }
+    }
+
+    if let Some(Value::Array(required)) = object.get("required") {
+        let properties = object.get("properties").and_then(Value::as_object);
+        let patterns = object.get("patternProperties").and_then(Value::as_object);
+        let closed = object.get("additionalProperties") == Some(&Value::Bool(false));
+
+        for name in required.iter().filter_map(Value::as_str) {
+            let described = properties.is_some_and(|properties| properties.contains_key(name))
+                || patterns.is_some_and(|patterns| {
+                    patterns
+                        .keys()
+                        .any(|pattern| pattern_matches(pattern, name))
+                });
+            if described {
+                continue;
+            }
+            if closed {
+                report(
+                    &["required"],
+                    LintRule::Unsatisfiable,
+                    Severity::Error,
+                    format!(
+                        "\"{name}\" is required but not allowed by \"additionalProperties\": false"
+                    ),
+                );
+            } else if properties.is_some() {
+                report(
+                    &["required"],
+                    LintRule::RequiredNotInProperties,
+                    Severity::Warning,
+                    format!("\"{name}\" is required but isn't in \"properties\""),
+                );
+            }
+        }
+    }
+
+    if let Some(Value::Array(branches)) = object.get("oneOf") {
+        for (index, branch) in branches.iter().enumerate() {
+            let index_segment = index.to_string();
+            let reason = if branch == &Value::Bool(false) {
+                Some("it is false, so nothing matches it".to_string())
+            } else if branches[..index].contains(branch) {
+                Some("it is the same as an earlier branch, so never matches alone".to_string())
+            } else {
+                branch
+                    .as_object()
+                    .map(types_of)
+                    .filter(|branch_types| {
+                        !types.is_empty()
+                            && !branch_types.is_empty()
+                            && !branch_types
+                                .iter()
+                                .any(|branch_type| types_overlap(branch_type, &types))
+                    })
+                    .map(|branch_types| {
+                        format!(
+                            "its type {} is never allowed by type {}",
+                            branch_types.join(" or "),
+                            types.join(" or ")
+                        )
+                    })
+            };
+            if let Some(reason) = reason {
+                report(
+                    &["oneOf", &index_segment],
+                    LintRule::UnreachableBranch,
+                    Severity::Warning,
+                    format!("\"oneOf\" branch {index} is unreachable: {reason}"),
+                );
+            }
+        }
+    }
+
+    for (keyword, value) in object {
+        let keyword_path = format!("{path}/{}", escape_pointer_segment(keyword));
+        if DATA_KEYWORDS.contains(&keyword.as_str()) {
+            continue;
+        }
+        if SCHEMA_KEYWORDS.contains(&keyword.as_str()) {
+            match value {
+                Value::Array(items) => lint_all(items.iter().enumerate(), &keyword_path, findings),
+                _ => lint(value, &keyword_path, findings),
+            }
+        } else if SCHEMA_ARRAY_KEYWORDS.contains(&keyword.as_str()) {
+            if let Value::Array(items) = value {
+                lint_all(items.iter().enumerate(), &keyword_path, findings);
+            }
+        } else if SCHEMA_MAP_KEYWORDS.contains(&keyword.as_str()) {
+            if let Value::Object(schemas) = value {
+                lint_map(schemas, &keyword_path, findings);
+            }
+        }
+    }
+}
+
+fn lint_all<'a, I>(schemas: I, path: &str, findings: &mut Vec<LintFinding>)
+where
+    I: Iterator<Item = (usize, &'a Value)>,
+{
+    for (index, schema) in schemas {
+        lint(schema, &format!("{path}/{index}"), findings);
+    }
+}
+
+fn lint_map(schemas: &Map<String, Value>, path: &str, findings: &mut Vec<LintFinding>) {
+    for (name, schema) in schemas {
+        lint(
+            schema,
+            &format!("{path}/{}", escape_pointer_segment(name)),
+            findings,
+        );
+    }
+}
+
+fn types_of(object: &Map<String, Value>) -> Vec<&str> {
+    match object.get("type") {
+        Some(Value::String(single)) => vec![single.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn types_overlap(candidate: &str, types: &[&str]) -> bool {
+    types.iter().any(|allowed| {
+        *allowed == candidate
+            || (*allowed == "number" && candidate == "integer")
+            || (*allowed == "integer" && candidate == "number")
+    })
+}
+
+fn has_any_type(value: &Value, types: &[&str]) -> bool {
+    types.iter().any(|allowed| match (*allowed, value) {
+        ("null", Value::Null)
+        | ("boolean", Value::Bool(_))
+        | ("string", Value::String(_))
+        | ("array", Value::Array(_))
+        | ("object", Value::Object(_))
+        | ("number", Value::Number(_)) => true,
+        ("integer", Value::Number(number)) => {
+            number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => false,
+    })
+}
+
+/// The known keyword an unknown one is most likely a misspelling of.
+fn closest_keyword(keyword: &str) -> Option<&'static str> {
+    let lowered = keyword.to_lowercase();
+    KNOWN_KEYWORDS
+        .iter()
+        .map(|known| (edit_distance(&lowered, &known.to_lowercase()), *known))
+        .filter(|(distance, _)| *distance <= 2 && *distance < keyword.len() / 2)
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance between two strings, counting an adjacent transposition as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut before_previous = previous.clone();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        before_previous = std::mem::replace(&mut previous, current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{lint_schema, LintRule, Severity};
+
+    fn rules_at(schema: serde_json::Value) -> Vec<(String, LintRule, Severity)> {
+        lint_schema(&schema)
+            .into_iter()
+            .map(|finding| (finding.schema_path, finding.rule, finding.severity))
+            .collect()
+    }
+
+    #[test]
+    fn well_formed_schemas_have_no_findings() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "dueOn": { "type": "string", "format": "date", "x-sensitive": true },
+                "amount": { "type": "number", "minimum": 0, "maximum": 100 },
+                "status": { "type": "string", "enum": ["open", "closed"] }
+            },
+            "required": ["dueOn"],
+            "oneOf": [{ "required": ["amount"] }, { "required": ["status"] }]
+        });
+
+        assert_eq!(lint_schema(&schema), vec![]);
+    }
+
+    #[test]
+    fn misspelt_keywords_are_errors() {
+        let findings = lint_schema(&json!({
+            "type": "object",
+            "requried": ["dueOn"],
+            "properties": {
+                "dueOn": { "type": "string", "fromat": "date" },
+                "notes": { "type": "string", "widget": "textarea" }
+            }
+        }));
+
+        let messages: Vec<String> = findings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error [unknown-keyword] at [/requried]: Unknown keyword \"requried\", did you \
+                 mean \"required\"?",
+                "error [unknown-keyword] at [/properties/dueOn/fromat]: Unknown keyword \
+                 \"fromat\", did you mean \"format\"?",
+                "warning [unknown-keyword] at [/properties/notes/widget]: Unknown keyword \
+                 \"widget\" is ignored by validators",
+            ]
+        );
+    }
+
+    #[test]
+    fn contradictions_and_bad_patterns_are_reported() {
+        let findings = rules_at(json!({
+            "type": "object",
+            "properties": {
+                "code": { "type": "string", "pattern": "[A-Z", "minLength": 5, "maxLength": 3 },
+                "kind": { "type": "integer", "enum": ["a", "b"] },
+                "dueOn": { "format": "date" }
+            },
+            "patternProperties": { "(unclosed": { "type": "string" } },
+            "required": ["code", "reference"],
+            "additionalProperties": false
+        }));
+
+        assert_eq!(
+            findings,
+            vec![
+                (
+                    "/patternProperties".to_string(),
+                    LintRule::InvalidRegex,
+                    Severity::Error
+                ),
+                (
+                    "/required".to_string(),
+                    LintRule::Unsatisfiable,
+                    Severity::Error
+                ),
+                (
+                    "/properties/code/pattern".to_string(),
+                    LintRule::InvalidRegex,
+                    Severity::Error
+                ),
+                (
+                    "/properties/code/minLength".to_string(),
+                    LintRule::Unsatisfiable,
+                    Severity::Error
+                ),
+                (
+                    "/properties/dueOn/format".to_string(),
+                    LintRule::FormatWithoutType,
+                    Severity::Warning
+                ),
+                (
+                    "/properties/kind/enum".to_string(),
+                    LintRule::Unsatisfiable,
+                    Severity::Error
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_properties_and_unreachable_branches_are_warnings() {
+        let findings = rules_at(json!({
+            "type": "object",
+            "properties": { "amount": { "type": "number" } },
+            "required": ["amount", "currency"],
+            "oneOf": [
+                { "type": "object", "required": ["amount"] },
+                { "type": "string" },
+                false,
+                { "type": "object", "required": ["amount"] }
+            ]
+        }));
+
+        assert_eq!(
+            findings,
+            vec![
+                (
+                    "/required".to_string(),
+                    LintRule::RequiredNotInProperties,
+                    Severity::Warning
+                ),
+                (
+                    "/oneOf/1".to_string(),
+                    LintRule::UnreachableBranch,
+                    Severity::Warning
+                ),
+                (
+                    "/oneOf/2".to_string(),
+                    LintRule::UnreachableBranch,
+                    Severity::Warning
+                ),
+                (
+                    "/oneOf/3".to_string(),
+                    LintRule::UnreachableBranch,
+                    Severity::Warning
+                ),
+            ]
+        );
+    }
+}
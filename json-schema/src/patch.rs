@@ -0,0 +1,486 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Number, Value};
+
+use crate::util::{self, escape_pointer_segment};
+use crate::validation::validate_json_report;
+
+/// A JSON Patch operation that could not be applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchError {
+    /// Index of the failing operation within the patch document.
+    pub index: usize,
+    /// The `path` of the failing operation.
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Json patch operation {} at [{}] failed: {}",
+            self.index, self.pointer, self.message
+        )
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Applies an RFC 7396 JSON Merge Patch to the target.
+///
+/// Objects in the patch are merged into the target recursively, a `null` removes the
+/// corresponding key, and any other value replaces the target value outright.
+pub fn apply_merge_patch(target: Value, patch: &Value) -> Value {
+    let Value::Object(patch) = patch else {
+        return patch.clone();
+    };
+
+    let mut target = match target {
+        Value::Object(target) => target,
+        _ => Map::new(),
+    };
+
+    for (key, patch_value) in patch {
+        if patch_value.is_null() {
+            target.remove(key);
+        } else {
+            let target_value = target.remove(key).unwrap_or(Value::Null);
+            target.insert(key.clone(), apply_merge_patch(target_value, patch_value));
+        }
+    }
+
+    Value::Object(target)
+}
+
+/// Applies an RFC 6902 JSON Patch to the document.
+///
+/// Operations are applied in order and the patch is all or nothing: if any operation fails, the
+/// error identifies it and no partially patched document is returned.
+pub fn apply_json_patch(document: Value, patch: &Value) -> Result<Value, PatchError> {
+    let operations = patch.as_array().ok_or_else(|| PatchError {
+        index: 0,
+        pointer: String::new(),
+        message: "Json patch must be an array of operations".to_string(),
+    })?;
+
+    operations
+        .iter()
+        .enumerate()
+        .try_fold(document, |document, (index, operation)| {
+            apply_operation(document, operation).map_err(|message| PatchError {
+                index,
+                pointer: operation
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                message,
+            })
+        })
+}
+
+/// Applies an RFC 7396 JSON Merge Patch and validates the result against the schema.
+///
+/// The patched document is only returned if it is valid, so a failed validation leaves the
+/// caller's document untouched. A validation failure is returned as a `ValidationReport` error.
+pub fn apply_merge_patch_validated(
+    document: &Value,
+    patch: &Value,
+    schema: &Value,
+) -> Result<Value> {
+    let patched = apply_merge_patch(document.clone(), patch);
+
+    validated(schema, patched)
+}
+
+/// Applies an RFC 6902 JSON Patch and validates the result against the schema.
+///
+/// The patched document is only returned if every operation succeeds and the result is valid,
+/// so a failure leaves the caller's document untouched. Failures are returned as a `PatchError`
+/// or a `ValidationReport` error.
+pub fn apply_json_patch_validated(
+    document: &Value,
+    patch: &Value,
+    schema: &Value,
+) -> Result<Value> {
+    let patched = apply_json_patch(document.clone(), patch)?;
+
+    validated(schema, patched)
+}
+
+/// Produces an RFC 6902 JSON Patch that transforms `a` into `b`.
+pub fn diff_to_json_patch(a: &Value, b: &Value) -> Value {
+    let mut operations = Vec::new();
+    diff_values(a, b, "", &mut operations);
+
+    Value::Array(operations)
+}
+
+fn validated(schema: &Value, patched: Value) -> Result<Value> {
+    validate_json_report(schema, &patched, None)?
+        .into_result()
+        .map_err(|report| anyhow!(report))?;
+
+    Ok(patched)
+}
+
+fn apply_operation(mut document: Value, operation: &Value) -> Result<Value, String> {
+    let op = member_str(operation, "op")?;
+    let path = parse_pointer(member_str(operation, "path")?)?;
+
+    match op {
+        "add" => add(&mut document, &path, member(operation, "value")?.clone())?,
+        "remove" => {
+            remove(&mut document, &path)?;
+        }
+        "replace" => {
+            let target = lookup_mut(&mut document, &path)?;
+            *target = member(operation, "value")?.clone();
+        }
+        "move" => {
+            let from = parse_pointer(member_str(operation, "from")?)?;
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err("Cannot move a value into one of its own children".to_string());
+            }
+            let value = remove(&mut document, &from)?;
+            add(&mut document, &path, value)?;
+        }
+        "copy" => {
+            let from = parse_pointer(member_str(operation, "from")?)?;
+            let value = lookup(&document, &from)?.clone();
+            add(&mut document, &path, value)?;
+        }
+        "test" => {
+            let expected = member(operation, "value")?;
+            if !json_equal(lookup(&document, &path)?, expected) {
+                return Err(format!("Value does not equal {expected}"));
+            }
+        }
+        other => return Err(format!("Unknown operation \"{other}\"")),
+    }
+
+    Ok(document)
+}
+
+fn member<'a>(operation: &'a Value, name: &str) -> Result<&'a Value, String> {
+    operation
+        .get(name)
+        .ok_or_else(|| format!("Operation is missing \"{name}\""))
+}
+
+fn member_str<'a>(operation: &'a Value, name: &str) -> Result<&'a str, String> {
+    member(operation, name)?
+        .as_str()
+        .ok_or_else(|| format!("Operation \"{name}\" must be a string"))
+}
+
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    util::parse_pointer(pointer).map_err(|err| err.to_string())
+}
+
+fn array_index(token: &str, len: usize) -> Result<usize, String> {
+    util::array_index(token)
+        .filter(|index| *index < len)
+        .ok_or_else(|| format!("Array index \"{token}\" is out of bounds"))
+}
+
+fn lookup<'a>(document: &'a Value, path: &[String]) -> Result<&'a Value, String> {
+    path.iter()
+        .try_fold(document, |current, token| match current {
+            Value::Object(object) => object
+                .get(token)
+                .ok_or_else(|| format!("Property \"{token}\" does not exist")),
+            Value::Array(items) => Ok(&items[array_index(token, items.len())?]),
+            _ => Err(format!("Cannot index into a scalar with \"{token}\"")),
+        })
+}
+
+fn lookup_mut<'a>(document: &'a mut Value, path: &[String]) -> Result<&'a mut Value, String> {
+    path.iter()
+        .try_fold(document, |current, token| match current {
+            Value::Object(object) => object
+                .get_mut(token)
+                .ok_or_else(|| format!("Property \"{token}\" does not exist")),
+            Value::Array(items) => {
+                let index = array_index(token, items.len())?;
+                Ok(&mut items[index])
+            }
+            _ => Err(format!("Cannot index into a scalar with \"{token}\"")),
+        })
+}
+
+fn add(document: &mut Value, path: &[String], value: Value) -> Result<(), String> {
+    let Some((last, parent_path)) = path.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+
+    match lookup_mut(document, parent_path)? {
+        Value::Object(object) => {
+            object.insert(last.clone(), value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => {
+            let index = array_index(last, items.len() + 1)?;
+            items.insert(index, value);
+        }
+        _ => return Err(format!("Cannot add \"{last}\" to a scalar")),
+    }
+
+    Ok(())
+}
+
+fn remove(document: &mut Value, path: &[String]) -> Result<Value, String> {
+    let Some((last, parent_path)) = path.split_last() else {
+        return Err("Cannot remove the whole document".to_string());
+    };
+
+    match lookup_mut(document, parent_path)? {
+        Value::Object(object) => object
+            .remove(last)
+            .ok_or_else(|| format!("Property \"{last}\" does not exist")),
+        Value::Array(items) => {
+            let index = array_index(last, items.len())?;
+            Ok(items.remove(index))
+        }
+        _ => Err(format!("Cannot remove \"{last}\" from a scalar")),
+    }
+}
+
+/// JSON equality as defined by RFC 6902, where numbers are compared by value so `1` equals `1.0`.
+pub(crate) fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => numbers_equal(a, b),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| json_equal(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// Integers are compared exactly, as large IDs beyond 2^53 can't be told apart as doubles. Only
+/// when either number is a float are they compared as doubles.
+fn numbers_equal(a: &Number, b: &Number) -> bool {
+    let integer = |number: &Number| {
+        number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from))
+    };
+
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.as_f64() == b.as_f64(),
+    }
+}
+
+fn diff_values(a: &Value, b: &Value, path: &str, operations: &mut Vec<Value>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, a_value) in a {
+                let child_path = format!("{path}/{}", escape_pointer_segment(key));
+                match b.get(key) {
+                    Some(b_value) => diff_values(a_value, b_value, &child_path, operations),
+                    None => operations.push(json!({ "op": "remove", "path": child_path })),
+                }
+            }
+            for (key, b_value) in b {
+                if !a.contains_key(key) {
+                    let child_path = format!("{path}/{}", escape_pointer_segment(key));
+                    operations.push(json!({ "op": "add", "path": child_path, "value": b_value }));
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for (index, (a_item, b_item)) in a.iter().zip(b).enumerate() {
+                diff_values(a_item, b_item, &format!("{path}/{index}"), operations);
+            }
+            // Remove from the end so earlier indices stay valid as the patch is applied.
+            for index in (b.len()..a.len()).rev() {
+                operations.push(json!({ "op": "remove", "path": format!("{path}/{index}") }));
+            }
+            for (index, b_item) in b.iter().enumerate().skip(a.len()) {
+                operations.push(
+                    json!({ "op": "add", "path": format!("{path}/{index}"), "value": b_item }),
+                );
+            }
+        }
+        (a, b) if json_equal(a, b) => {}
+        (_, b) => operations.push(json!({ "op": "replace", "path": path, "value": b })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assertables::assert_starts_with;
+    use serde_json::json;
+
+    use super::{
+        apply_json_patch, apply_json_patch_validated, apply_merge_patch, diff_to_json_patch,
+        PatchError,
+    };
+    use crate::report::ValidationReport;
+
+    #[test]
+    fn apply_merge_patch_follows_rfc_7396() {
+        let target = json!({
+            "title": "Goodbye!",
+            "author": { "givenName": "John", "familyName": "Doe" },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        let patch = json!({
+            "title": "Hello!",
+            "phoneNumber": "+01-123-456-7890",
+            "author": { "familyName": null },
+            "tags": ["example"]
+        });
+
+        let result = apply_merge_patch(target, &patch);
+
+        assert_eq!(
+            result,
+            json!({
+                "title": "Hello!",
+                "author": { "givenName": "John" },
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+    }
+
+    #[test]
+    fn apply_merge_patch_replaces_non_object_values() {
+        assert_eq!(
+            apply_merge_patch(json!({ "a": 1 }), &json!([1])),
+            json!([1])
+        );
+        assert_eq!(
+            apply_merge_patch(json!([1]), &json!({ "a": 1 })),
+            json!({ "a": 1 })
+        );
+    }
+
+    #[test]
+    fn apply_json_patch_applies_every_operation() {
+        let document = json!({ "a": { "b": [1, 2] }, "c": "d", "m~n": 1 });
+        let patch = json!([
+            { "op": "add", "path": "/a/b/1", "value": 5 },
+            { "op": "add", "path": "/a/b/-", "value": 9 },
+            { "op": "remove", "path": "/c" },
+            { "op": "replace", "path": "/m~0n", "value": 2 },
+            { "op": "copy", "from": "/a/b", "path": "/copied" },
+            { "op": "move", "from": "/copied/0", "path": "/moved" },
+            { "op": "test", "path": "/moved", "value": 1.0 }
+        ]);
+
+        let result = apply_json_patch(document, &patch).unwrap();
+
+        assert_eq!(
+            result,
+            json!({ "a": { "b": [1, 5, 2, 9] }, "m~n": 2, "copied": [5, 2, 9], "moved": 1 })
+        );
+    }
+
+    #[test]
+    fn apply_json_patch_reports_the_failing_operation() {
+        let document = json!({ "a": [1] });
+        let patch = json!([
+            { "op": "add", "path": "/b", "value": 1 },
+            { "op": "replace", "path": "/a/3", "value": 1 }
+        ]);
+
+        let result = apply_json_patch(document, &patch);
+
+        assert_eq!(
+            result,
+            Err(PatchError {
+                index: 1,
+                pointer: "/a/3".to_string(),
+                message: "Array index \"3\" is out of bounds".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn apply_json_patch_fails_a_test_operation_that_does_not_match() {
+        let result = apply_json_patch(
+            json!({ "a": "x" }),
+            &json!([{ "op": "test", "path": "/a", "value": "y" }]),
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Json patch operation 0 at [/a] failed: Value does not equal \"y\""
+        );
+    }
+
+    #[test]
+    fn apply_json_patch_validated_only_returns_valid_documents() {
+        let schema = json!({ "properties": { "age": { "type": "integer" } } });
+        let document = json!({ "age": 30 });
+
+        let valid = apply_json_patch_validated(
+            &document,
+            &json!([{ "op": "replace", "path": "/age", "value": 31 }]),
+            &schema,
+        );
+        let invalid = apply_json_patch_validated(
+            &document,
+            &json!([{ "op": "replace", "path": "/age", "value": "old" }]),
+            &schema,
+        );
+
+        assert_eq!(valid.unwrap(), json!({ "age": 31 }));
+        let error = invalid.unwrap_err();
+        let report = error.downcast_ref::<ValidationReport>().unwrap();
+        assert_eq!(report.issues[0].instance_path, "/age");
+        assert_starts_with!(error.to_string(), "Json failed validation, error(s):");
+    }
+
+    #[test]
+    fn diff_to_json_patch_produces_a_patch_that_round_trips() {
+        let a = json!({ "a": 1, "b": { "c": [1, 2, 3] }, "d/e": true, "same": 1 });
+        let b = json!({ "a": 2, "b": { "c": [1, 4] }, "f": null, "same": 1.0 });
+
+        let patch = diff_to_json_patch(&a, &b);
+
+        assert_eq!(
+            patch,
+            json!([
+                { "op": "replace", "path": "/a", "value": 2 },
+                { "op": "replace", "path": "/b/c/1", "value": 4 },
+                { "op": "remove", "path": "/b/c/2" },
+                { "op": "remove", "path": "/d~1e" },
+                { "op": "add", "path": "/f", "value": null }
+            ])
+        );
+        assert_eq!(
+            apply_json_patch(a, &patch).unwrap(),
+            json!({ "a": 2, "b": { "c": [1, 4] }, "f": null, "same": 1 })
+        );
+    }
+
+    #[test]
+    fn diff_to_json_patch_compares_large_integers_exactly() {
+        let a = json!({ "id": 9007199254740993_u64, "big": u64::MAX, "float": 1.0 });
+        let b = json!({ "id": 9007199254740992_u64, "big": u64::MAX, "float": 1 });
+
+        let patch = diff_to_json_patch(&a, &b);
+
+        assert_eq!(
+            patch,
+            json!([{ "op": "replace", "path": "/id", "value": 9007199254740992_u64 }])
+        );
+        let test = json!([{ "op": "test", "path": "/id", "value": 9007199254740992_u64 }]);
+        assert!(apply_json_patch(a, &test).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::inspector::SchemaInspector;
+use crate::util::escape_pointer_segment;
+use crate::walk::{BranchMode, SchemaNode, SchemaWalker};
+
+/// Options controlling which properties `prune` removes.
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Keep properties that aren't declared but that the schema doesn't forbid either, because
+    /// it says nothing about `additionalProperties`. By default they're removed.
+    pub keep_undeclared: bool,
+}
+
+/// The result of `prune`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pruned {
+    /// The instance without the removed properties.
+    pub instance: Value,
+    /// JSON pointers to the properties removed, outer objects' before those of the objects
+    /// within them.
+    pub removed: Vec<String>,
+}
+
+/// Removes the properties of the instance's objects that the schema doesn't allow.
+///
+/// A property is kept when a schema applying to its object declares it in `properties` or
+/// matches it with `patternProperties`, or when one allows extra properties through an
+/// `additionalProperties` or `unevaluatedProperties` other than `false`. It's removed, however it's
+/// allowed, when a schema with `additionalProperties: false` doesn't declare it itself, or when
+/// a schema has `unevaluatedProperties: false` and none declares it. References are followed,
+/// and `allOf` always applies, while only the branches of `oneOf`, `anyOf`, `if` and
+/// `dependentSchemas` the value matches are used. Where a value matches no branch of a `oneOf` or
+/// `anyOf`, the properties of all its branches are kept, as it's unknown which was meant.
+///
+/// Objects whose schemas declare no properties at all, such as free-form maps, are left as they
+/// are.
+///
+/// # Arguments
+///
+/// * `inspector` - The inspector for the schema to prune against
+/// * `instance` - The instance to prune
+/// * `options` - Which undeclared properties to remove
+pub fn prune(
+    inspector: &SchemaInspector,
+    instance: &Value,
+    options: &PruneOptions,
+) -> Result<Pruned> {
+    let mut pruned = instance.clone();
+    let mut removed = Vec::new();
+
+    inspector.visit(
+        &mut pruned,
+        BranchMode::Matching,
+        &mut |walker, nodes, value, path| {
+            if !value.is_object() {
+                return Ok(());
+            }
+
+            let mut describing = nodes.to_vec();
+            describing.extend(walker.unmatched_branches(nodes, value)?);
+            if !describing.iter().any(declares_properties) {
+                return Ok(());
+            }
+
+            let Value::Object(object) = value else {
+                unreachable!("The value was an object");
+            };
+            object.retain(|key, _| {
+                let allowed = is_allowed(walker, nodes, &describing, key, options);
+                if !allowed {
+                    removed.push(format!("{path}/{}", escape_pointer_segment(key)));
+                }
+                allowed
+            });
+
+            Ok(())
+        },
+    )?;
+
+    Ok(Pruned {
+        instance: pruned,
+        removed,
+    })
+}
+
+fn declares_properties(node: &SchemaNode) -> bool {
+    [
+        "properties",
+        "patternProperties",
+        "additionalProperties",
+        "unevaluatedProperties",
+    ]
+    .iter()
+    .any(|keyword| node.get(keyword).is_some())
+}
+
+/// Whether the key is kept. Any of the `describing` schemas can allow it, but only the `nodes`
+/// that apply to the object can forbid it, not the branches it matched none of.
+fn is_allowed(
+    walker: &SchemaWalker,
+    nodes: &[SchemaNode],
+    describing: &[SchemaNode],
+    key: &str,
+    options: &PruneOptions,
+) -> bool {
+    let is_false = |node: &SchemaNode, keyword| node.get(keyword) == Some(&Value::Bool(false));
+    let evaluated = describing.iter().any(|node| {
+        declares(walker, node, key)
+            || node
+                .get("additionalProperties")
+                .is_some_and(|schema| *schema != Value::Bool(false))
+    });
+
+    let forbidden = nodes.iter().any(|node| {
+        (is_false(node, "additionalProperties") && !declares(walker, node, key))
+            || (is_false(node, "unevaluatedProperties") && !evaluated)
+    });
+    if forbidden {
+        return false;
+    }
+
+    evaluated
+        || describing
+            .iter()
+            .any(|node| match node.get("unevaluatedProperties") {
+                Some(schema) => *schema != Value::Bool(false),
+                None => {
+                    options.keep_undeclared
+                        && declares_properties(node)
+                        && node.get("additionalProperties").is_none()
+                }
+            })
+}
+
+/// Whether the node declares the key in `properties` or matches it with `patternProperties`.
+fn declares(walker: &SchemaWalker, node: &SchemaNode, key: &str) -> bool {
+    let declared = node
+        .get("properties")
+        .and_then(Value::as_object)
+        .is_some_and(|properties| properties.contains_key(key));
+    let matched = node
+        .get("patternProperties")
+        .and_then(Value::as_object)
+        .is_some_and(|patterns| {
+            patterns
+                .keys()
+                .any(|pattern| walker.pattern_matches(pattern, key))
+        });
+
+    declared || matched
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{prune, PruneOptions};
+    use crate::inspector::SchemaInspector;
+
+    fn inspector() -> SchemaInspector {
+        SchemaInspector::new(&json!({
+            "type": "object",
+            "properties": {
+                "reference": { "type": "string" },
+                "customer": { "$ref": "#/$defs/customer" },
+                "metadata": { "type": "object" },
+                "payment": {
+                    "oneOf": [
+                        {
+                            "properties": {
+                                "kind": { "const": "card" },
+                                "last4": { "type": "string" }
+                            },
+                            "required": ["kind"]
+                        },
+                        {
+                            "properties": {
+                                "kind": { "const": "bank" },
+                                "sortCode": { "type": "string" }
+                            },
+                            "required": ["kind"]
+                        }
+                    ]
+                }
+            },
+            "patternProperties": { "^x-": { "type": "string" } },
+            "$defs": {
+                "customer": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } },
+                    "additionalProperties": false
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn prune_removes_undeclared_properties() {
+        let pruned = prune(
+            &inspector(),
+            &json!({
+                "reference": "CASE-1",
+                "internalId": 42,
+                "x-trace": "abc",
+                "customer": { "name": "Jane Doe", "ssn": "123" },
+                "metadata": { "anything": true }
+            }),
+            &PruneOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            pruned.instance,
+            json!({
+                "reference": "CASE-1",
+                "x-trace": "abc",
+                "customer": { "name": "Jane Doe" },
+                "metadata": { "anything": true }
+            })
+        );
+        assert_eq!(pruned.removed, vec!["/internalId", "/customer/ssn"]);
+    }
+
+    #[test]
+    fn prune_uses_the_matching_branch() {
+        let pruned = prune(
+            &inspector(),
+            &json!({ "payment": { "kind": "bank", "sortCode": "12-34-56", "last4": "1234" } }),
+            &PruneOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            pruned.instance,
+            json!({ "payment": { "kind": "bank", "sortCode": "12-34-56" } })
+        );
+        assert_eq!(pruned.removed, vec!["/payment/last4"]);
+    }
+
+    #[test]
+    fn prune_keeps_every_branchs_properties_when_none_match() {
+        let pruned = prune(
+            &inspector(),
+            &json!({ "payment": { "kind": "cash", "last4": "1234", "note": "x" } }),
+            &PruneOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            pruned.instance,
+            json!({ "payment": { "kind": "cash", "last4": "1234" } })
+        );
+        assert_eq!(pruned.removed, vec!["/payment/note"]);
+    }
+
+    #[test]
+    fn prune_can_keep_undeclared_properties_the_schema_allows() {
+        let pruned = prune(
+            &inspector(),
+            &json!({ "internalId": 42, "customer": { "ssn": "123" } }),
+            &PruneOptions {
+                keep_undeclared: true,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(pruned.instance, json!({ "internalId": 42, "customer": {} }));
+        assert_eq!(pruned.removed, vec!["/customer/ssn"]);
+    }
+
+    #[test]
+    fn prune_lets_additional_properties_false_veto_other_schemas() {
+        let schema = json!({
+            "properties": { "a": {} },
+            "additionalProperties": false,
+            "allOf": [{ "properties": { "b": {} } }]
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+
+        let pruned = prune(
+            &inspector,
+            &json!({ "a": 1, "b": 2 }),
+            &PruneOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(pruned.instance, json!({ "a": 1 }));
+        assert_eq!(pruned.removed, vec!["/b"]);
+        assert!(inspector.is_valid(&pruned.instance).unwrap());
+
+        let schema = json!({
+            "properties": { "a": {} },
+            "unevaluatedProperties": false,
+            "allOf": [{ "properties": { "b": {} } }]
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+
+        let pruned = prune(
+            &inspector,
+            &json!({ "a": 1, "b": 2, "c": 3 }),
+            &PruneOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(pruned.instance, json!({ "a": 1, "b": 2 }));
+        assert!(inspector.is_valid(&pruned.instance).unwrap());
+    }
+}
@@ -0,0 +1,392 @@
+use anyhow::Result;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::inspector::SchemaInspector;
+use crate::report::ValidationReport;
+use crate::util::remove_path;
+use crate::walk::BranchMode;
+
+/// The schema annotation marking a value as sensitive.
+pub const SENSITIVE_KEYWORD: &str = "x-sensitive";
+
+const MASK: &str = "[REDACTED]";
+
+/// What a sensitive value is replaced with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedactionStyle {
+    /// Replaced with `"[REDACTED]"`.
+    Mask,
+    /// Strings keep their last `visible` characters, with the rest replaced by `*`. Other values
+    /// are masked.
+    Partial { visible: usize },
+    /// Replaced with `"sha256:"` and the hex SHA-256 of the salt followed by the value's JSON, so
+    /// that equal values can still be matched up across logs.
+    Hash { salt: String },
+    /// Removed from its object or array.
+    Remove,
+}
+
+/// Options controlling which values `redact` treats as sensitive and how it hides them.
+#[derive(Debug, Clone)]
+pub struct RedactionOptions {
+    pub style: RedactionStyle,
+    /// Values with one of these formats are sensitive even without `x-sensitive: true`.
+    pub sensitive_formats: Vec<String>,
+}
+
+impl Default for RedactionOptions {
+    fn default() -> Self {
+        RedactionOptions {
+            style: RedactionStyle::Mask,
+            sensitive_formats: ["iban", "uk-sort-code", "uk-account-number"]
+                .map(str::to_string)
+                .to_vec(),
+        }
+    }
+}
+
+/// The result of `redact`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redaction {
+    pub instance: Value,
+    /// JSON pointers to the values that were redacted.
+    pub redacted: Vec<String>,
+}
+
+/// Hides every value in the instance whose schema marks it as sensitive, with `x-sensitive: true`
+/// or one of `options.sensitive_formats`, so the instance can be logged.
+///
+/// Values are treated as sensitive if any `anyOf`, `oneOf` or `if` branch marks them, and a
+/// sensitive object or array is redacted as a whole.
+pub fn redact(
+    inspector: &SchemaInspector,
+    instance: &Value,
+    options: &RedactionOptions,
+) -> Result<Redaction> {
+    let mut redacted_instance = instance.clone();
+    let mut redacted = Vec::new();
+
+    inspector.visit(
+        &mut redacted_instance,
+        BranchMode::All,
+        &mut |_, nodes, value, path| {
+            let sensitive = nodes.iter().any(|node| {
+                node.get(SENSITIVE_KEYWORD) == Some(&Value::Bool(true))
+                    || node
+                        .get("format")
+                        .and_then(Value::as_str)
+                        .is_some_and(|format| options.sensitive_formats.iter().any(|f| f == format))
+            });
+            if sensitive {
+                *value = redact_value(value, &options.style);
+                redacted.push(path.to_string());
+            }
+            Ok(())
+        },
+    )?;
+
+    if options.style == RedactionStyle::Remove {
+        // Later siblings come later in the list, so removing in reverse keeps array indices valid.
+        // A sensitive root can't be removed, so is left nulled.
+        for path in redacted.iter().rev().filter(|path| !path.is_empty()) {
+            remove_path(&mut redacted_instance, path)?;
+        }
+    }
+
+    Ok(Redaction {
+        instance: redacted_instance,
+        redacted,
+    })
+}
+
+/// Redacts the sensitive values a validation report carries, both in each issue's `value` and
+/// where an issue's message quotes them.
+///
+/// Messages are only rewritten where they quote a value's JSON whole. The message of an issue
+/// about a sensitive value that doesn't quote it whole is replaced with one naming the keyword.
+pub fn redact_report(
+    inspector: &SchemaInspector,
+    instance: &Value,
+    report: &ValidationReport,
+    options: &RedactionOptions,
+) -> Result<ValidationReport> {
+    let redaction = redact(inspector, instance, options)?;
+    let masked = Value::String(MASK.to_string());
+
+    // The JSON of each sensitive value and what to show instead, for rewriting messages.
+    let replacements: Vec<(String, String)> = redaction
+        .redacted
+        .iter()
+        .filter_map(|path| {
+            let original = instance.pointer(path)?;
+            let replacement = redaction.instance.pointer(path).unwrap_or(&masked);
+            Some((original.to_string(), replacement.to_string()))
+        })
+        .collect();
+
+    let mut redacted_report = report.clone();
+    for issue in &mut redacted_report.issues {
+        let touches_sensitive = redaction.redacted.iter().any(|path| {
+            path == &issue.instance_path || path.starts_with(&format!("{}/", issue.instance_path))
+        });
+        if !touches_sensitive {
+            continue;
+        }
+
+        let value = match instance.pointer(&issue.instance_path) {
+            Some(original) if *original == issue.value => redaction
+                .instance
+                .pointer(&issue.instance_path)
+                .cloned()
+                .unwrap_or_else(|| masked.clone()),
+            _ if options.style == RedactionStyle::Remove => masked.clone(),
+            _ => redact_value(&issue.value, &options.style),
+        };
+        let quoted = replace_quoted(&issue.message, &issue.value.to_string(), &value.to_string());
+        issue.message = match quoted {
+            Some(message) => message,
+            // The message doesn't quote the sensitive value whole, so could show part of it.
+            None if redaction.redacted.contains(&issue.instance_path) => {
+                format!("{value} is not valid under {}", issue.keyword)
+            }
+            None => issue.message.clone(),
+        };
+        for (original, replacement) in &replacements {
+            if let Some(message) = replace_quoted(&issue.message, original, replacement) {
+                issue.message = message;
+            }
+        }
+        issue.value = value;
+    }
+
+    Ok(redacted_report)
+}
+
+/// Replaces the places a message quotes a value's JSON, or `None` if it doesn't. Only whole
+/// quotes count, so a value of `1` isn't found in `10` or `1.5`.
+fn replace_quoted(message: &str, original: &str, replacement: &str) -> Option<String> {
+    let mut replaced = String::with_capacity(message.len());
+    let mut rest = 0;
+    for (start, _) in message.match_indices(original) {
+        let end = start + original.len();
+        let before = message[..start].chars().next_back();
+        let after = message[end..].chars().next();
+        if !matches!(before, None | Some(' ' | '('))
+            || !matches!(after, None | Some(' ' | ')' | ','))
+        {
+            continue;
+        }
+        replaced.push_str(&message[rest..start]);
+        replaced.push_str(replacement);
+        rest = end;
+    }
+    if rest == 0 {
+        return None;
+    }
+    replaced.push_str(&message[rest..]);
+
+    Some(replaced)
+}
+
+fn redact_value(value: &Value, style: &RedactionStyle) -> Value {
+    match (style, value) {
+        (RedactionStyle::Partial { visible }, Value::String(text)) => {
+            let length = text.chars().count();
+            // Strings no longer than the visible part are hidden entirely.
+            let hidden = if length > *visible {
+                length - visible
+            } else {
+                length
+            };
+            let shown: String = text.chars().skip(hidden).collect();
+            Value::String(format!("{}{shown}", "*".repeat(hidden)))
+        }
+        (RedactionStyle::Hash { salt }, _) => {
+            let digest = Sha256::new()
+                .chain_update(salt.as_bytes())
+                .chain_update(value.to_string().as_bytes())
+                .finalize();
+            let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+            Value::String(format!("sha256:{hex}"))
+        }
+        // Removed values are nulled until they're taken out of their parent.
+        (RedactionStyle::Remove, _) => Value::Null,
+        _ => Value::String(MASK.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{redact, redact_report, RedactionOptions, RedactionStyle};
+    use crate::inspector::SchemaInspector;
+    use crate::report::ValidationIssue;
+    use crate::validation::validate_json_report;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "x-sensitive": true },
+                "reference": { "type": "string" },
+                "bank": {
+                    "type": "object",
+                    "properties": {
+                        "iban": { "type": "string", "format": "iban" },
+                        "sortCode": { "type": "string", "maxLength": 8, "format": "uk-sort-code" }
+                    }
+                },
+                "phones": { "type": "array", "items": { "$ref": "#/$defs/phone" } }
+            },
+            "required": ["reference"],
+            "$defs": {
+                "phone": { "type": "string", "x-sensitive": true }
+            }
+        })
+    }
+
+    fn instance() -> serde_json::Value {
+        json!({
+            "name": "Jane Doe",
+            "reference": "CASE-1",
+            "bank": { "iban": "GB82WEST12345698765432", "sortCode": "12-34-56" },
+            "phones": ["07700900123", "07700900456"]
+        })
+    }
+
+    #[test]
+    fn redact_masks_sensitive_values() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+
+        let redaction = redact(&inspector, &instance(), &RedactionOptions::default()).unwrap();
+
+        assert_eq!(
+            redaction.instance,
+            json!({
+                "name": "[REDACTED]",
+                "reference": "CASE-1",
+                "bank": { "iban": "[REDACTED]", "sortCode": "[REDACTED]" },
+                "phones": ["[REDACTED]", "[REDACTED]"]
+            })
+        );
+        assert_eq!(
+            redaction.redacted,
+            vec![
+                "/bank/iban",
+                "/bank/sortCode",
+                "/name",
+                "/phones/0",
+                "/phones/1"
+            ]
+        );
+    }
+
+    #[test]
+    fn redact_supports_partial_hashed_and_removed_styles() {
+        let inspector = SchemaInspector::new(&schema()).unwrap();
+        let with_style = |style| RedactionOptions {
+            style,
+            ..Default::default()
+        };
+
+        let partial = redact(
+            &inspector,
+            &instance(),
+            &with_style(RedactionStyle::Partial { visible: 4 }),
+        )
+        .unwrap();
+        let hashed = redact(
+            &inspector,
+            &instance(),
+            &with_style(RedactionStyle::Hash {
+                salt: "pepper".to_string(),
+            }),
+        )
+        .unwrap();
+        let removed = redact(&inspector, &instance(), &with_style(RedactionStyle::Remove)).unwrap();
+
+        assert_eq!(
+            partial.instance["bank"]["iban"],
+            json!("******************5432")
+        );
+        assert_eq!(partial.instance["phones"][0], json!("*******0123"));
+        let hash = hashed.instance["name"].as_str().unwrap();
+        assert!(hash.starts_with("sha256:") && hash.len() == 71);
+        assert_ne!(hashed.instance["phones"][0], hashed.instance["phones"][1]);
+        assert_eq!(
+            removed.instance,
+            json!({ "reference": "CASE-1", "bank": {}, "phones": [] })
+        );
+    }
+
+    #[test]
+    fn redact_report_hides_sensitive_values_in_messages() {
+        let schema = schema();
+        let inspector = SchemaInspector::new(&schema).unwrap();
+        let mut instance = instance();
+        instance["bank"]["sortCode"] = json!("12-34-56-78");
+        instance.as_object_mut().unwrap().remove("reference");
+
+        let report = validate_json_report(&schema, &instance, None).unwrap();
+        let redacted =
+            redact_report(&inspector, &instance, &report, &RedactionOptions::default()).unwrap();
+
+        let text = redacted.to_string();
+        assert_eq!(redacted.issues.len(), 2);
+        assert!(!text.contains("12-34-56-78"), "{text}");
+        assert!(!text.contains("Jane Doe"), "{text}");
+        assert!(
+            text.contains("\"[REDACTED]\" is longer than 8 characters"),
+            "{text}"
+        );
+        let value_at = |path: &str| {
+            redacted
+                .issues
+                .iter()
+                .find(|issue| issue.instance_path == path)
+                .map(|issue| issue.value.clone())
+                .unwrap()
+        };
+        assert_eq!(value_at("")["name"], json!("[REDACTED]"));
+        assert_eq!(value_at("/bank/sortCode"), json!("[REDACTED]"));
+    }
+
+    #[test]
+    fn redact_report_only_rewrites_whole_quotes_of_sensitive_values() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "pin": { "type": "integer", "minimum": 10, "x-sensitive": true },
+                "code": { "type": "string", "x-sensitive": true }
+            }
+        });
+        let inspector = SchemaInspector::new(&schema).unwrap();
+        let instance = json!({ "pin": 1, "code": "AB-5678" });
+        let mut report = validate_json_report(&schema, &instance, None).unwrap();
+        report.issues.push(ValidationIssue {
+            instance_path: "/code".to_string(),
+            schema_path: "/properties/code/x-check".to_string(),
+            keyword: "x-check".to_string(),
+            message: "code ending 5678 is not allowed".to_string(),
+            value: json!("AB-5678"),
+        });
+
+        let redacted =
+            redact_report(&inspector, &instance, &report, &RedactionOptions::default()).unwrap();
+
+        let messages: Vec<&str> = redacted
+            .issues
+            .iter()
+            .map(|issue| issue.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "\"[REDACTED]\" is less than the minimum of 10",
+                "\"[REDACTED]\" is not valid under x-check",
+            ]
+        );
+    }
+}
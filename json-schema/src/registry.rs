@@ -0,0 +1,534 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use jsonschema::{Draft, Registry, Resource, Retrieve, Uri, ValidationOptions, Validator};
+use referencing::{Resolver, ResourceRef};
+use serde_json::Value;
+
+use crate::util::escape_pointer_segment;
+
+/// The base URI schemas loaded from a directory are registered under, so that a schema at
+/// `common/address.json` can be referenced as `flexys://schemas/common/address.json`, or
+/// relatively as `common/address.json` from any schema that doesn't declare its own absolute `$id`.
+pub const SCHEMA_BASE_URI: &str = "flexys://schemas/";
+
+/// Keywords whose values are instance data rather than sub-schemas, so are never searched for
+/// `$ref`s.
+pub(crate) const DATA_KEYWORDS: [&str; 4] = ["const", "default", "enum", "examples"];
+
+/// Keywords whose value is an object of sub-schemas, keyed by names rather than keywords.
+pub(crate) const SCHEMA_MAP_KEYWORDS: [&str; 6] = [
+    "$defs",
+    "definitions",
+    "dependencies",
+    "dependentSchemas",
+    "patternProperties",
+    "properties",
+];
+
+/// Keywords that don't affect validation, so a schema made up of only these plus a `$ref` is a
+/// plain alias of whatever it refers to.
+const ANNOTATION_KEYWORDS: [&str; 7] = [
+    "$comment",
+    "$id",
+    "$schema",
+    "description",
+    "examples",
+    "title",
+    "default",
+];
+
+/// A set of JSON schemas that can reference each other by `$id` or by relative path.
+///
+/// References are only ever resolved against the registered schemas: nothing is retrieved over
+/// the network or from outside the loaded directory.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<String, Value>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every `.json` file below the directory, registering each under its path relative to
+    /// the directory and under its `$id`, if it has one.
+    ///
+    /// Fails if any file can't be parsed, or if any `$ref` within the loaded schemas is
+    /// unresolvable or part of a reference cycle.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut registry = Self::new();
+
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            let entries = fs::read_dir(&current).with_context(|| {
+                format!("Failed to read schema directory {}", current.display())
+            })?;
+
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path
+                    .extension()
+                    .is_some_and(|extension| extension == "json")
+                {
+                    let contents = fs::read_to_string(&path)
+                        .with_context(|| format!("Failed to read schema {}", path.display()))?;
+                    let schema: Value = serde_json::from_str(&contents)
+                        .with_context(|| format!("Failed to parse schema {}", path.display()))?;
+                    let relative_path = path
+                        .strip_prefix(dir)?
+                        .components()
+                        .map(|component| component.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+
+                    registry.register(&relative_path, schema)?;
+                }
+            }
+        }
+
+        registry.check_references()?;
+
+        Ok(registry)
+    }
+
+    /// Registers a schema under the given URI. Relative URIs, such as `common/address.json`, are
+    /// registered beneath `SCHEMA_BASE_URI`. If the schema declares an `$id` it can also be
+    /// referenced by that.
+    pub fn register(&mut self, uri: &str, schema: Value) -> Result<()> {
+        let uri = absolute_uri(uri);
+        Resource::from_contents(schema.clone())
+            .map_err(|err| anyhow!("Invalid json schema {uri}, error: {err}"))?;
+
+        self.schemas.insert(uri, schema);
+
+        Ok(())
+    }
+
+    /// Looks up a registered schema by its registered URI, relative path or `$id`.
+    pub fn get(&self, uri: &str) -> Option<&Value> {
+        let uri = absolute_uri(uri);
+
+        self.schemas.get(&uri).or_else(|| {
+            self.schemas.values().find(|schema| {
+                schema
+                    .get("$id")
+                    .and_then(Value::as_str)
+                    .is_some_and(|id| id.trim_end_matches('#') == uri)
+            })
+        })
+    }
+
+    /// The URIs schemas were registered under.
+    pub fn uris(&self) -> impl Iterator<Item = &str> {
+        self.schemas.keys().map(String::as_str)
+    }
+
+    /// Checks that every `$ref` within the registered schemas resolves, and that none of them
+    /// form a cycle of references that never reaches an actual schema.
+    pub fn check_references(&self) -> Result<()> {
+        let registry = self.build_registry(None)?;
+
+        for (uri, schema) in &self.schemas {
+            let resolver = registry.try_resolver(uri)?;
+            check_references_within(uri, schema, "", Draft::default(), &resolver)?;
+        }
+
+        Ok(())
+    }
+
+    /// Validation options that resolve references against the registered schemas only.
+    pub fn validation_options(&self) -> Result<ValidationOptions> {
+        Ok(jsonschema::options()
+            .with_registry(self.build_registry(None)?)
+            .with_retriever(OfflineRetriever))
+    }
+
+    /// Compiles a validator for the schema, resolving its references against the registered
+    /// schemas. Relative references resolve from `SCHEMA_BASE_URI` unless the schema declares its
+    /// own `$id`.
+    pub fn validator_for(&self, schema: &Value) -> Result<Validator> {
+        let root_uri = schema_uri(schema, SCHEMA_BASE_URI);
+        let registry = self.build_registry(Some((&root_uri, schema)))?;
+        let resolver = registry.try_resolver(&root_uri)?;
+        check_references_within(&root_uri, schema, "", Draft::default(), &resolver)?;
+
+        self.validation_options()?
+            .with_base_uri(root_uri)
+            .build(schema)
+            .map_err(|err| anyhow!("Invalid json schema, error: {err}"))
+    }
+
+    /// Compiles a validator for one of the registered schemas.
+    pub fn validator_for_uri(&self, uri: &str) -> Result<Validator> {
+        let schema = self
+            .get(uri)
+            .ok_or_else(|| anyhow!("No schema registered for {uri}"))?;
+
+        self.validation_options()?
+            .with_base_uri(absolute_uri(uri))
+            .build(schema)
+            .map_err(|err| anyhow!("Invalid json schema {uri}, error: {err}"))
+    }
+
+    /// Builds a `referencing` registry of the registered schemas, plus a root schema that isn't
+    /// itself registered, for resolving references while walking a schema.
+    pub(crate) fn build_registry(&self, root: Option<(&str, &Value)>) -> Result<Registry> {
+        let mut resources = Vec::with_capacity(self.schemas.len() + 1);
+        for (uri, schema) in root.into_iter().chain(
+            self.schemas
+                .iter()
+                .map(|(uri, schema)| (uri.as_str(), schema)),
+        ) {
+            let resource = Resource::from_contents(schema.clone())
+                .map_err(|err| anyhow!("Invalid json schema {uri}, error: {err}"))?;
+            resources.push((uri.to_string(), resource));
+        }
+
+        Registry::options()
+            .retriever(OfflineRetriever)
+            .build(resources)
+            .map_err(|err| anyhow!("Unresolvable schema reference, error: {err}"))
+    }
+}
+
+/// Refuses to retrieve any schema that isn't already registered.
+pub(crate) struct OfflineRetriever;
+
+impl Retrieve for OfflineRetriever {
+    fn retrieve(
+        &self,
+        uri: &Uri<String>,
+    ) -> std::result::Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        Err(format!("{uri} is not a registered schema").into())
+    }
+}
+
+pub(crate) fn absolute_uri(uri: &str) -> String {
+    let uri = uri.trim_end_matches('#');
+    if uri.contains(':') {
+        uri.to_string()
+    } else {
+        format!("{SCHEMA_BASE_URI}{}", uri.trim_start_matches('/'))
+    }
+}
+
+/// The URI a schema is identified by: its own `$id` if it is absolute, otherwise the default.
+pub(crate) fn schema_uri(schema: &Value, default: &str) -> String {
+    match schema.get("$id").and_then(Value::as_str) {
+        Some(id) if id.contains(':') => id.trim_end_matches('#').to_string(),
+        _ => default.to_string(),
+    }
+}
+
+fn check_references_within(
+    document_uri: &str,
+    schema: &Value,
+    pointer: &str,
+    draft: Draft,
+    resolver: &Resolver,
+) -> Result<()> {
+    match schema {
+        Value::Object(object) => {
+            let draft = draft.detect(schema).unwrap_or(draft);
+            let resolver = resolver
+                .in_subresource(ResourceRef::new(schema, draft))
+                .map_err(|err| anyhow!("Invalid $id in {document_uri}#{pointer}, error: {err}"))?;
+
+            if let Some(reference) = object.get("$ref").and_then(Value::as_str) {
+                check_reference(document_uri, pointer, reference, &resolver)?;
+            }
+
+            for (key, value) in object {
+                if DATA_KEYWORDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let pointer = format!("{pointer}/{}", escape_pointer_segment(key));
+                match value {
+                    // The keys are names, so are never keywords.
+                    Value::Object(schemas) if SCHEMA_MAP_KEYWORDS.contains(&key.as_str()) => {
+                        for (name, schema) in schemas {
+                            let pointer = format!("{pointer}/{}", escape_pointer_segment(name));
+                            check_references_within(
+                                document_uri,
+                                schema,
+                                &pointer,
+                                draft,
+                                &resolver,
+                            )?;
+                        }
+                    }
+                    _ => check_references_within(document_uri, value, &pointer, draft, &resolver)?,
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                let pointer = format!("{pointer}/{index}");
+                check_references_within(document_uri, item, &pointer, draft, resolver)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Follows a reference, and any chain of references it leads through, failing if the chain
+/// can't be resolved or loops back on itself.
+fn check_reference(
+    document_uri: &str,
+    pointer: &str,
+    reference: &str,
+    resolver: &Resolver,
+) -> Result<()> {
+    let mut chain = vec![format!("{document_uri}#{pointer}")];
+    let mut visited = HashSet::new();
+    let mut resolver = resolver.clone();
+    let mut reference = reference.to_string();
+
+    loop {
+        let resolved = resolver.lookup(&reference).map_err(|err| {
+            anyhow!(
+                "Unresolvable reference \"{reference}\" in {}, error: {err}",
+                chain.last().expect("Chain always has a starting point")
+            )
+        })?;
+        let (contents, next_resolver, _) = resolved.into_inner();
+
+        chain.push(reference.clone());
+        if !visited.insert(contents as *const Value) {
+            bail!("Reference cycle detected: {}", chain.join(" -> "));
+        }
+
+        match alias_target(contents) {
+            Some(next) => {
+                reference = next.to_string();
+                resolver = next_resolver;
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// The reference a schema is purely an alias for, if it has no validation of its own.
+fn alias_target(schema: &Value) -> Option<&str> {
+    let object = schema.as_object()?;
+    let reference = object.get("$ref")?.as_str()?;
+
+    object
+        .keys()
+        .all(|key| key == "$ref" || ANNOTATION_KEYWORDS.contains(&key.as_str()))
+        .then_some(reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use assertables::{assert_contains, assert_starts_with};
+    use serde_json::{json, Value};
+
+    use super::SchemaRegistry;
+
+    fn write_schema(dir: &Path, relative_path: &str, schema: Value) {
+        let path = dir.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, serde_json::to_string_pretty(&schema).unwrap()).unwrap();
+    }
+
+    fn common_schemas(dir: &Path) {
+        write_schema(
+            dir,
+            "common/address.json",
+            json!({
+                "type": "object",
+                "properties": {
+                    "line1": { "type": "string" },
+                    "postcode": { "$ref": "postcode.json" }
+                },
+                "required": ["line1"]
+            }),
+        );
+        write_schema(
+            dir,
+            "common/postcode.json",
+            json!({ "type": "string", "maxLength": 8 }),
+        );
+        write_schema(
+            dir,
+            "money.json",
+            json!({
+                "$id": "https://schemas.flexys.com/money.json",
+                "type": "object",
+                "properties": {
+                    "amount": { "type": "number" },
+                    "currency": { "type": "string" }
+                }
+            }),
+        );
+    }
+
+    #[test]
+    fn load_dir_registers_schemas_by_relative_path_and_id() {
+        let dir = tempfile::tempdir().unwrap();
+        common_schemas(dir.path());
+
+        let registry = SchemaRegistry::load_dir(dir.path()).unwrap();
+
+        assert!(registry.get("common/address.json").is_some());
+        assert!(registry
+            .get("flexys://schemas/common/postcode.json")
+            .is_some());
+        assert!(registry
+            .get("https://schemas.flexys.com/money.json")
+            .is_some());
+        assert!(registry.get("missing.json").is_none());
+    }
+
+    #[test]
+    fn validator_for_resolves_references_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        common_schemas(dir.path());
+        let registry = SchemaRegistry::load_dir(dir.path()).unwrap();
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "address": { "$ref": "common/address.json" },
+                "balance": { "$ref": "https://schemas.flexys.com/money.json" }
+            }
+        });
+
+        let validator = registry.validator_for(&schema).unwrap();
+
+        assert!(validator.is_valid(&json!({
+            "address": { "line1": "1 High Street", "postcode": "AB1 2CD" },
+            "balance": { "amount": 10.5, "currency": "GBP" }
+        })));
+        assert!(!validator.is_valid(&json!({
+            "address": { "line1": "1 High Street", "postcode": "far too long" }
+        })));
+        assert!(!validator.is_valid(&json!({ "balance": { "amount": "ten" } })));
+    }
+
+    #[test]
+    fn validator_for_uri_compiles_a_registered_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        common_schemas(dir.path());
+        let registry = SchemaRegistry::load_dir(dir.path()).unwrap();
+
+        let validator = registry.validator_for_uri("common/address.json").unwrap();
+
+        assert!(validator.is_valid(&json!({ "line1": "1 High Street" })));
+        assert!(!validator.is_valid(&json!({ "postcode": "AB1 2CD" })));
+    }
+
+    #[test]
+    fn load_dir_fails_on_reference_to_unregistered_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        write_schema(
+            dir.path(),
+            "contact.json",
+            json!({ "properties": { "phone": { "$ref": "phone.json" } } }),
+        );
+
+        let result = SchemaRegistry::load_dir(dir.path());
+
+        let message = result.unwrap_err().to_string();
+        assert_starts_with!(message, "Unresolvable schema reference");
+        assert_contains!(message, "flexys://schemas/phone.json");
+    }
+
+    #[test]
+    fn load_dir_fails_on_reference_to_missing_definition() {
+        let dir = tempfile::tempdir().unwrap();
+        write_schema(
+            dir.path(),
+            "contact.json",
+            json!({
+                "$defs": { "email": { "type": "string" } },
+                "properties": { "phone": { "$ref": "#/$defs/phone" } }
+            }),
+        );
+
+        let result = SchemaRegistry::load_dir(dir.path());
+
+        assert_starts_with!(
+            result.unwrap_err().to_string(),
+            "Unresolvable reference \"#/$defs/phone\" in flexys://schemas/contact.json#/properties/phone"
+        );
+    }
+
+    #[test]
+    fn load_dir_checks_properties_named_like_data_keywords() {
+        let dir = tempfile::tempdir().unwrap();
+        write_schema(
+            dir.path(),
+            "contact.json",
+            json!({ "properties": { "default": { "$ref": "#/$defs/phone" } } }),
+        );
+
+        let result = SchemaRegistry::load_dir(dir.path());
+
+        assert_starts_with!(
+            result.unwrap_err().to_string(),
+            "Unresolvable reference \"#/$defs/phone\" in flexys://schemas/contact.json#/properties/default"
+        );
+
+        write_schema(
+            dir.path(),
+            "contact.json",
+            json!({ "$defs": { "enum": { "$ref": "#/$defs/enum" } } }),
+        );
+
+        let result = SchemaRegistry::load_dir(dir.path());
+
+        assert_starts_with!(
+            result.unwrap_err().to_string(),
+            "Reference cycle detected: flexys://schemas/contact.json#/$defs/enum"
+        );
+    }
+
+    #[test]
+    fn load_dir_fails_on_reference_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        write_schema(dir.path(), "a.json", json!({ "$ref": "b.json" }));
+        write_schema(dir.path(), "b.json", json!({ "$ref": "a.json" }));
+
+        let result = SchemaRegistry::load_dir(dir.path());
+
+        assert_starts_with!(
+            result.unwrap_err().to_string(),
+            "Reference cycle detected: flexys://schemas/a.json# -> b.json -> a.json -> b.json"
+        );
+    }
+
+    #[test]
+    fn recursive_schemas_are_not_reference_cycles() {
+        let mut registry = SchemaRegistry::new();
+        registry
+            .register(
+                "node.json",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "children": { "type": "array", "items": { "$ref": "node.json" } }
+                    }
+                }),
+            )
+            .unwrap();
+
+        registry.check_references().unwrap();
+        let validator = registry.validator_for_uri("node.json").unwrap();
+
+        assert!(validator.is_valid(&json!({ "children": [{ "children": [] }] })));
+        assert!(!validator.is_valid(&json!({ "children": [{ "children": 1 }] })));
+    }
+}
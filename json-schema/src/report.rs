@@ -0,0 +1,278 @@
+use std::fmt;
+
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::{ValidationError, Validator};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::util::unescape_pointer_segment;
+
+/// A single failure found while validating an instance against a JSON schema.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    /// JSON pointer to the value within the instance that failed validation.
+    pub instance_path: String,
+    /// JSON pointer to the schema keyword that rejected the value.
+    pub schema_path: String,
+    /// The schema keyword that failed, e.g. `type`, `required` or `format`.
+    pub keyword: String,
+    /// Human readable description of the failure.
+    pub message: String,
+    /// The offending value taken from the instance.
+    pub value: Value,
+}
+
+impl ValidationIssue {
+    pub fn from_validation_error(error: &ValidationError) -> Self {
+        ValidationIssue {
+            instance_path: error.instance_path.as_str().to_string(),
+            schema_path: error.schema_path.as_str().to_string(),
+            keyword: keyword_of(error),
+            message: error.to_string(),
+            value: error.instance.clone().into_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Validation Error [{}]. Schema Path [{}]. Instance Path [{}].",
+            self.message, self.schema_path, self.instance_path
+        )
+    }
+}
+
+/// The structured result of validating an instance against a JSON schema.
+///
+/// An empty report means the instance is valid. `truncated` is set when collection stopped at
+/// the configured error cap, in which case further issues may exist beyond those listed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+    pub truncated: bool,
+}
+
+impl ValidationReport {
+    /// Runs the validator over the instance and collects its errors into a report.
+    ///
+    /// # Arguments
+    ///
+    /// * `validator`: A compiled jsonschema `Validator`.
+    /// * `instance`: The JSON value to validate.
+    /// * `max_errors`: The maximum number of issues to collect, or `None` to collect them all.
+    ///
+    pub fn collect(validator: &Validator, instance: &Value, max_errors: Option<usize>) -> Self {
+        let mut report = ValidationReport::default();
+
+        for error in validator.iter_errors(instance) {
+            if max_errors.is_some_and(|max| report.issues.len() >= max) {
+                report.truncated = true;
+                break;
+            }
+            report
+                .issues
+                .push(ValidationIssue::from_validation_error(&error));
+        }
+
+        report
+    }
+
+    /// Adds issues found outside the validator, such as by custom keywords, stopping at the same
+    /// error cap as `collect`.
+    pub fn extend<I>(&mut self, issues: I, max_errors: Option<usize>)
+    where
+        I: IntoIterator<Item = ValidationIssue>,
+    {
+        if self.truncated {
+            return;
+        }
+        for issue in issues {
+            if max_errors.is_some_and(|max| self.issues.len() >= max) {
+                self.truncated = true;
+                break;
+            }
+            self.issues.push(issue);
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Converts the report into a `Result`, failing with the report itself if it has any issues.
+    pub fn into_result(self) -> Result<(), ValidationReport> {
+        if self.is_valid() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return f.write_str("Json passed validation");
+        }
+
+        f.write_str("Json failed validation, error(s): ")?;
+        for (index, issue) in self.issues.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{issue}")?;
+        }
+        if self.truncated {
+            f.write_str(" (further errors omitted)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+fn keyword_of(error: &ValidationError) -> String {
+    let keyword = match &error.kind {
+        ValidationErrorKind::AdditionalItems { .. } => "additionalItems",
+        ValidationErrorKind::AdditionalProperties { .. } => "additionalProperties",
+        ValidationErrorKind::AnyOf => "anyOf",
+        ValidationErrorKind::BacktrackLimitExceeded { .. } => "pattern",
+        ValidationErrorKind::Constant { .. } => "const",
+        ValidationErrorKind::Contains => "contains",
+        ValidationErrorKind::ContentEncoding { .. } => "contentEncoding",
+        ValidationErrorKind::ContentMediaType { .. } => "contentMediaType",
+        ValidationErrorKind::Enum { .. } => "enum",
+        ValidationErrorKind::ExclusiveMaximum { .. } => "exclusiveMaximum",
+        ValidationErrorKind::ExclusiveMinimum { .. } => "exclusiveMinimum",
+        ValidationErrorKind::FalseSchema => "false",
+        ValidationErrorKind::Format { .. } => "format",
+        ValidationErrorKind::FromUtf8 { .. } => "contentEncoding",
+        ValidationErrorKind::MaxItems { .. } => "maxItems",
+        ValidationErrorKind::Maximum { .. } => "maximum",
+        ValidationErrorKind::MaxLength { .. } => "maxLength",
+        ValidationErrorKind::MaxProperties { .. } => "maxProperties",
+        ValidationErrorKind::MinItems { .. } => "minItems",
+        ValidationErrorKind::Minimum { .. } => "minimum",
+        ValidationErrorKind::MinLength { .. } => "minLength",
+        ValidationErrorKind::MinProperties { .. } => "minProperties",
+        ValidationErrorKind::MultipleOf { .. } => "multipleOf",
+        ValidationErrorKind::Not { .. } => "not",
+        ValidationErrorKind::OneOfMultipleValid | ValidationErrorKind::OneOfNotValid => "oneOf",
+        ValidationErrorKind::Pattern { .. } => "pattern",
+        ValidationErrorKind::PropertyNames { .. } => "propertyNames",
+        ValidationErrorKind::Required { .. } => "required",
+        ValidationErrorKind::Type { .. } => "type",
+        ValidationErrorKind::UnevaluatedItems { .. } => "unevaluatedItems",
+        ValidationErrorKind::UnevaluatedProperties { .. } => "unevaluatedProperties",
+        ValidationErrorKind::UniqueItems => "uniqueItems",
+        ValidationErrorKind::Referencing(_) => "$ref",
+        // Custom keywords are named by the last segment of the path they were registered at.
+        ValidationErrorKind::Custom { .. } => {
+            return error
+                .schema_path
+                .as_str()
+                .rsplit('/')
+                .next()
+                .map(unescape_pointer_segment)
+                .unwrap_or_default()
+        }
+    };
+
+    keyword.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonschema::validator_for;
+    use serde_json::json;
+
+    use super::ValidationReport;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number" },
+                "y": { "type": "string", "format": "date" }
+            },
+            "required": ["z"]
+        })
+    }
+
+    #[test]
+    fn collect_reports_each_issue_with_paths_keyword_and_value() {
+        let validator = validator_for(&schema()).unwrap();
+        let instance = json!({ "x": "wibble", "z": 1 });
+
+        let report = ValidationReport::collect(&validator, &instance, None);
+
+        assert_eq!(report.issues.len(), 1);
+        let issue = &report.issues[0];
+        assert_eq!(issue.instance_path, "/x");
+        assert_eq!(issue.schema_path, "/properties/x/type");
+        assert_eq!(issue.keyword, "type");
+        assert_eq!(issue.message, r#""wibble" is not of type "number""#);
+        assert_eq!(issue.value, json!("wibble"));
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn collect_stops_at_the_error_cap_and_marks_the_report_truncated() {
+        let validator = validator_for(&schema()).unwrap();
+        let instance = json!({ "x": "wibble" });
+
+        let report = ValidationReport::collect(&validator, &instance, Some(1));
+
+        assert_eq!(report.issues.len(), 1);
+        assert!(report.truncated);
+    }
+
+    #[test]
+    fn collect_of_valid_instance_is_valid() {
+        let validator = validator_for(&schema()).unwrap();
+        let instance = json!({ "x": 1, "z": true });
+
+        let report = ValidationReport::collect(&validator, &instance, Some(1));
+
+        assert!(report.is_valid());
+        assert_eq!(report.into_result(), Ok(()));
+    }
+
+    #[test]
+    fn report_serializes_issues_in_camel_case() {
+        let validator = validator_for(&schema()).unwrap();
+
+        let report = ValidationReport::collect(&validator, &json!([]), None);
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            json!({
+                "issues": [{
+                    "instancePath": "",
+                    "schemaPath": "/type",
+                    "keyword": "type",
+                    "message": r#"[] is not of type "object""#,
+                    "value": []
+                }],
+                "truncated": false
+            })
+        );
+    }
+
+    #[test]
+    fn report_displays_every_issue() {
+        let validator = validator_for(&schema()).unwrap();
+        let instance = json!({ "x": "wibble", "y": 1 });
+
+        let report = ValidationReport::collect(&validator, &instance, None);
+
+        assert_eq!(
+            report.to_string(),
+            r#"Json failed validation, error(s): Validation Error ["wibble" is not of type "number"]. Schema Path [/properties/x/type]. Instance Path [/x]., Validation Error [1 is not of type "string"]. Schema Path [/properties/y/type]. Instance Path [/y]., Validation Error ["z" is a required property]. Schema Path [/required]. Instance Path []."#
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::report::ValidationReport;
+use crate::util::escape_pointer_segment;
+use crate::validation::validate_json_report;
+
+/// A type that knows the JSON schema its serialized form must satisfy.
+pub trait TypedSchema {
+    /// The JSON schema instances of the type are validated against before deserializing.
+    fn json_schema() -> Value;
+}
+
+/// Why `validate_into` couldn't produce a typed value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidateIntoError {
+    /// The schema itself could not be compiled.
+    InvalidSchema(String),
+    /// The value failed schema validation.
+    Validation(ValidationReport),
+    /// The value passed validation but doesn't fit the type, e.g. because the schema is looser
+    /// than the type.
+    Deserialization {
+        /// JSON pointer to the value serde rejected.
+        instance_path: String,
+        message: String,
+    },
+}
+
+impl ValidateIntoError {
+    /// JSON pointers to the values the error is about.
+    pub fn instance_paths(&self) -> Vec<&str> {
+        match self {
+            ValidateIntoError::InvalidSchema(_) => Vec::new(),
+            ValidateIntoError::Validation(report) => report
+                .issues
+                .iter()
+                .map(|issue| issue.instance_path.as_str())
+                .collect(),
+            ValidateIntoError::Deserialization { instance_path, .. } => vec![instance_path],
+        }
+    }
+}
+
+impl fmt::Display for ValidateIntoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidateIntoError::InvalidSchema(message) => f.write_str(message),
+            ValidateIntoError::Validation(report) => write!(f, "{report}"),
+            ValidateIntoError::Deserialization {
+                instance_path,
+                message,
+            } => write!(
+                f,
+                "Json failed deserialization, error: {message}. Instance Path [{instance_path}]."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidateIntoError {}
+
+/// Validates the value against the schema, then deserializes it into `T`, so that both kinds of
+/// failure are reported the same way, with JSON pointers to the offending values.
+///
+/// # Arguments
+///
+/// * `schema`: A reference to the JSON schema `Value`.
+/// * `value`: The JSON value to validate and deserialize.
+///
+pub fn validate_into<T: DeserializeOwned>(
+    schema: &Value,
+    value: &Value,
+) -> Result<T, ValidateIntoError> {
+    let report = validate_json_report(schema, value, None)
+        .map_err(|err| ValidateIntoError::InvalidSchema(err.to_string()))?;
+    report
+        .into_result()
+        .map_err(ValidateIntoError::Validation)?;
+
+    deserialize(value)
+}
+
+/// Validates and deserializes the value like `validate_into`, using the schema `T` provides.
+pub fn validate_typed<T>(value: &Value) -> Result<T, ValidateIntoError>
+where
+    T: TypedSchema + DeserializeOwned,
+{
+    validate_into(&T::json_schema(), value)
+}
+
+/// Deserializes a value that has already been validated, reporting where it doesn't fit `T`.
+pub(crate) fn deserialize<T: DeserializeOwned>(value: &Value) -> Result<T, ValidateIntoError> {
+    serde_path_to_error::deserialize(value).map_err(|err| ValidateIntoError::Deserialization {
+        instance_path: pointer_of(err.path()),
+        message: err.inner().to_string(),
+    })
+}
+
+fn pointer_of(path: &serde_path_to_error::Path) -> String {
+    path.iter()
+        .filter_map(|segment| match segment {
+            serde_path_to_error::Segment::Seq { index } => Some(index.to_string()),
+            serde_path_to_error::Segment::Map { key } => Some(escape_pointer_segment(key)),
+            serde_path_to_error::Segment::Enum { .. } | serde_path_to_error::Segment::Unknown => {
+                None
+            }
+        })
+        .map(|segment| format!("/{segment}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::{validate_into, validate_typed, TypedSchema, ValidateIntoError};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Payment {
+        reference: String,
+        amount: u32,
+        lines: Vec<Line>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Line {
+        quantity: u8,
+    }
+
+    impl TypedSchema for Payment {
+        fn json_schema() -> Value {
+            json!({
+                "type": "object",
+                "properties": {
+                    "reference": { "type": "string", "minLength": 1 },
+                    "amount": { "type": "integer", "minimum": 1 },
+                    "lines": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": { "quantity": { "type": "integer" } },
+                            "required": ["quantity"]
+                        }
+                    }
+                },
+                "required": ["reference", "amount", "lines"]
+            })
+        }
+    }
+
+    #[test]
+    fn validate_typed_returns_the_deserialized_value() {
+        let payment: Payment = validate_typed(&json!({
+            "reference": "CASE-1",
+            "amount": 250,
+            "lines": [{ "quantity": 2 }]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            payment,
+            Payment {
+                reference: "CASE-1".to_string(),
+                amount: 250,
+                lines: vec![Line { quantity: 2 }],
+            }
+        );
+    }
+
+    #[test]
+    fn validation_failures_come_before_deserialization() {
+        let err = validate_typed::<Payment>(&json!({ "reference": "", "amount": 0, "lines": [] }))
+            .unwrap_err();
+
+        let ValidateIntoError::Validation(report) = &err else {
+            panic!("expected a validation error, got {err:?}");
+        };
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(err.instance_paths(), vec!["/amount", "/reference"]);
+    }
+
+    #[test]
+    fn deserialization_failures_are_reported_with_their_path() {
+        let err = validate_typed::<Payment>(&json!({
+            "reference": "CASE-1",
+            "amount": 250,
+            "lines": [{ "quantity": 2 }, { "quantity": 300 }]
+        }))
+        .unwrap_err();
+
+        assert_eq!(err.instance_paths(), vec!["/lines/1/quantity"]);
+        assert!(err
+            .to_string()
+            .starts_with("Json failed deserialization, error: invalid value: integer `300`"));
+        assert!(err
+            .to_string()
+            .ends_with("Instance Path [/lines/1/quantity]."));
+    }
+
+    #[test]
+    fn invalid_schemas_are_reported() {
+        let err = validate_into::<Payment>(&json!({ "type": "wibble" }), &json!({})).unwrap_err();
+
+        assert!(matches!(err, ValidateIntoError::InvalidSchema(_)));
+        assert!(err.to_string().starts_with("Invalid json schema, error: "));
+    }
+}
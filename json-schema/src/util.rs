@@ -1,8 +1,11 @@
-use serde_json::Value;
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use serde_json_path::JsonPath;
 
 /// Determines if a specific field within the "properties" section of a JSON schema is a date field.
 ///
-/// It checks if the specified field is a string and has the format "date".
+/// It checks if the specified field is a string, or may be one, and has the format "date".
 ///
 /// # Arguments
 ///
@@ -29,6 +32,11 @@ pub fn is_date_field(schema: &Value, field_name: &str) -> bool {
                     if type_str != "string" {
                         return false;
                     }
+                } else if let Some(types) = type_value.as_array() {
+                    // Optional fields are typed `["string", "null"]`.
+                    if !types.iter().any(|type_value| type_value == "string") {
+                        return false;
+                    }
                 } else {
                     return false;
                 }
@@ -49,7 +57,219 @@ pub fn is_date_field(schema: &Value, field_name: &str) -> bool {
     false
 }
 
+/// Escapes a property name for use as an RFC 6901 JSON pointer reference token.
+pub fn escape_pointer_segment(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+/// Turns an RFC 6901 JSON pointer reference token back into the property name it escapes.
+pub fn unescape_pointer_segment(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+/// Splits an RFC 6901 JSON pointer into its unescaped reference tokens. The empty pointer refers
+/// to the whole document and has no tokens.
+pub fn parse_pointer(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(tokens) = pointer.strip_prefix('/') else {
+        bail!("Invalid json pointer \"{pointer}\"");
+    };
+
+    Ok(tokens.split('/').map(unescape_pointer_segment).collect())
+}
+
+/// Parses a reference token as an array index, which RFC 6901 allows no sign or leading zeros.
+pub(crate) fn array_index(token: &str) -> Option<usize> {
+    let valid = !token.is_empty()
+        && token.bytes().all(|byte| byte.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+
+    valid.then(|| token.parse().ok()).flatten()
+}
+
+/// The value at the RFC 6901 JSON pointer, or `None` if there's nothing there.
+///
+/// # Arguments
+///
+/// * `value`: The JSON value to look in.
+/// * `pointer`: The JSON pointer, e.g. `/orders/0/reference`.
+///
+/// # Returns
+///
+/// An error if the pointer is malformed.
+pub fn get_path<'a>(value: &'a Value, pointer: &str) -> Result<Option<&'a Value>> {
+    let tokens = parse_pointer(pointer)?;
+
+    Ok(tokens
+        .iter()
+        .try_fold(value, |current, token| match current {
+            Value::Object(object) => object.get(token),
+            Value::Array(items) => items.get(array_index(token)?),
+            _ => None,
+        }))
+}
+
+/// A mutable reference to the value at the RFC 6901 JSON pointer, or `None` if there's nothing
+/// there.
+pub fn get_path_mut<'a>(value: &'a mut Value, pointer: &str) -> Result<Option<&'a mut Value>> {
+    let tokens = parse_pointer(pointer)?;
+
+    Ok(lookup_mut(value, &tokens))
+}
+
+fn lookup_mut<'a>(value: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens
+        .iter()
+        .try_fold(value, |current, token| match current {
+            Value::Object(object) => object.get_mut(token),
+            Value::Array(items) => items.get_mut(array_index(token)?),
+            _ => None,
+        })
+}
+
+/// Sets the value at the RFC 6901 JSON pointer, returning the value it replaced.
+///
+/// The parent object or array must already exist, see `ensure_path` to create it. In an array,
+/// an index within the array replaces that item, while `-` or the index one past the end appends.
+///
+/// # Arguments
+///
+/// * `target`: The JSON value to change.
+/// * `pointer`: The JSON pointer to set the value at.
+/// * `value`: The value to set.
+///
+/// # Returns
+///
+/// An error if the pointer is malformed, or the parent doesn't exist or can't hold the value.
+pub fn set_path(target: &mut Value, pointer: &str, value: Value) -> Result<Option<Value>> {
+    let tokens = parse_pointer(pointer)?;
+    let Some((last, parent)) = tokens.split_last() else {
+        return Ok(Some(std::mem::replace(target, value)));
+    };
+
+    match lookup_mut(target, parent) {
+        Some(Value::Object(object)) => Ok(object.insert(last.clone(), value)),
+        Some(Value::Array(items)) => {
+            let index = item_index(items, last, pointer)?;
+            if index == items.len() {
+                items.push(value);
+                Ok(None)
+            } else {
+                Ok(Some(std::mem::replace(&mut items[index], value)))
+            }
+        }
+        Some(_) => bail!("Cannot set \"{last}\" on a scalar at json pointer \"{pointer}\""),
+        None => bail!("Json pointer \"{pointer}\" has no parent to set the value in"),
+    }
+}
+
+/// Removes the value at the RFC 6901 JSON pointer, returning it, or `None` if there was nothing
+/// there. Later items of an array shift down.
+///
+/// # Returns
+///
+/// An error if the pointer is malformed or is the empty pointer, as the whole document can't be
+/// removed.
+pub fn remove_path(target: &mut Value, pointer: &str) -> Result<Option<Value>> {
+    let tokens = parse_pointer(pointer)?;
+    let Some((last, parent)) = tokens.split_last() else {
+        bail!("Cannot remove the whole document");
+    };
+
+    Ok(match lookup_mut(target, parent) {
+        Some(Value::Object(object)) => object.remove(last),
+        Some(Value::Array(items)) => array_index(last)
+            .filter(|index| *index < items.len())
+            .map(|index| items.remove(index)),
+        _ => None,
+    })
+}
+
+/// A mutable reference to the value at the RFC 6901 JSON pointer, creating it as `null` if
+/// it's missing, along with any missing parents as objects.
+///
+/// A `null` along the way is replaced with an object. In an array, `-` or the index one past the
+/// end appends a new item.
+///
+/// # Returns
+///
+/// An error if the pointer is malformed or runs into a scalar or past the end of an array.
+pub fn ensure_path<'a>(target: &'a mut Value, pointer: &str) -> Result<&'a mut Value> {
+    let tokens = parse_pointer(pointer)?;
+
+    tokens.iter().try_fold(target, |current, token| {
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+        match current {
+            Value::Object(object) => Ok(object.entry(token.clone()).or_insert(Value::Null)),
+            Value::Array(items) => {
+                let index = item_index(items, token, pointer)?;
+                if index == items.len() {
+                    items.push(Value::Null);
+                }
+                Ok(&mut items[index])
+            }
+            _ => bail!("Cannot create \"{token}\" in a scalar at json pointer \"{pointer}\""),
+        }
+    })
+}
+
+/// The index a token refers to in an array, allowing one past the end.
+fn item_index(items: &[Value], token: &str, pointer: &str) -> Result<usize> {
+    let index = match token {
+        "-" => items.len(),
+        token => array_index(token)
+            .filter(|index| *index <= items.len())
+            .ok_or_else(|| {
+                anyhow!("Array index \"{token}\" is out of bounds at json pointer \"{pointer}\"")
+            })?,
+    };
+
+    Ok(index)
+}
+
+/// A value found by an RFC 9535 JSONPath query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathMatch<'a> {
+    /// The normalized path to the value, e.g. `$['orders'][0]`.
+    pub path: String,
+    /// The JSON pointer to the value, e.g. `/orders/0`.
+    pub pointer: String,
+    pub value: &'a Value,
+}
+
+/// Queries the value with an RFC 9535 JSONPath expression, returning each match with its
+/// normalized path, in the order the query produces them.
+///
+/// # Arguments
+///
+/// * `value`: The JSON value to query.
+/// * `path`: The JSONPath expression, e.g. `$.orders[?@.total > 100].reference`.
+///
+/// # Returns
+///
+/// An error if the expression is malformed.
+pub fn query_json_path<'a>(value: &'a Value, path: &str) -> Result<Vec<PathMatch<'a>>> {
+    let query = JsonPath::parse(path)
+        .map_err(|err| anyhow!("Invalid json path \"{path}\", error: {err}"))?;
+
+    Ok(query
+        .query_located(value)
+        .iter()
+        .map(|node| PathMatch {
+            path: node.location().to_string(),
+            pointer: node.location().to_json_pointer(),
+            value: node.node(),
+        })
+        .collect())
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use serde_json::json;
@@ -80,6 +300,24 @@ mod tests {
         assert_eq!(is_date_field(&schema, "date_field"), false);
     }
 
+    #[test]
+    fn test_is_date_field_nullable() {
+        let schema = json!({
+            "properties": {
+                "date_field": {
+                    "type": ["string", "null"],
+                    "format": "date"
+                },
+                "other_field": {
+                    "type": ["integer", "null"],
+                    "format": "date"
+                }
+            }
+        });
+        assert_eq!(is_date_field(&schema, "date_field"), true);
+        assert_eq!(is_date_field(&schema, "other_field"), false);
+    }
+
     #[test]
     fn test_is_date_field_wrong_format() {
         let schema = json!({
@@ -144,4 +382,79 @@ mod tests {
         });
         assert_eq!(is_date_field(&schema, "any_field"), false);
     }
+
+    #[test]
+    fn test_get_path_follows_pointers() {
+        let value = json!({ "orders": [{ "a/b": 1, "m~n": 2 }] });
+
+        assert_eq!(get_path(&value, "/orders/0/a~1b").unwrap(), Some(&json!(1)));
+        assert_eq!(get_path(&value, "/orders/0/m~0n").unwrap(), Some(&json!(2)));
+        assert_eq!(get_path(&value, "").unwrap(), Some(&value));
+        assert_eq!(get_path(&value, "/orders/01").unwrap(), None);
+        assert_eq!(get_path(&value, "/missing").unwrap(), None);
+        assert!(get_path(&value, "orders").is_err());
+    }
+
+    #[test]
+    fn test_set_and_remove_path() {
+        let mut value = json!({ "orders": [1, 2] });
+
+        assert_eq!(
+            set_path(&mut value, "/orders/0", json!(10)).unwrap(),
+            Some(json!(1))
+        );
+        assert_eq!(set_path(&mut value, "/orders/-", json!(3)).unwrap(), None);
+        assert_eq!(
+            set_path(&mut value, "/status", json!("open")).unwrap(),
+            None
+        );
+        assert!(set_path(&mut value, "/orders/5", json!(5)).is_err());
+        assert!(set_path(&mut value, "/customer/name", json!("Jane")).is_err());
+        assert_eq!(value, json!({ "orders": [10, 2, 3], "status": "open" }));
+
+        assert_eq!(
+            remove_path(&mut value, "/orders/1").unwrap(),
+            Some(json!(2))
+        );
+        assert_eq!(remove_path(&mut value, "/missing").unwrap(), None);
+        assert!(remove_path(&mut value, "").is_err());
+        assert_eq!(value, json!({ "orders": [10, 3], "status": "open" }));
+    }
+
+    #[test]
+    fn test_ensure_path_creates_missing_parents() {
+        let mut value = json!({ "customer": null, "tags": [] });
+
+        *ensure_path(&mut value, "/customer/address/postcode").unwrap() = json!("SW1A 1AA");
+        *ensure_path(&mut value, "/tags/-").unwrap() = json!("vip");
+        ensure_path(&mut value, "/tags/0").unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "customer": { "address": { "postcode": "SW1A 1AA" } },
+                "tags": ["vip"]
+            })
+        );
+        assert!(ensure_path(&mut value, "/tags/0/name").is_err());
+        assert!(ensure_path(&mut value, "/tags/3").is_err());
+    }
+
+    #[test]
+    fn test_query_json_path_returns_normalized_paths() {
+        let value = json!({
+            "orders": [
+                { "reference": "A1", "total": 50 },
+                { "reference": "A2", "total": 150 }
+            ]
+        });
+
+        let matches = query_json_path(&value, "$.orders[?@.total > 100].reference").unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&matches).unwrap(),
+            json!([{ "path": "$['orders'][1]['reference']", "pointer": "/orders/1/reference", "value": "A2" }])
+        );
+        assert!(query_json_path(&value, "$.orders[").is_err());
+    }
 }
@@ -1,44 +1,336 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
 use anyhow::{anyhow, bail, Result};
 use jsonschema::validator_for;
-use serde_json::Value;
-
+use serde_json::{Map, Value};
+
+use crate::inspector::SchemaInspector;
+use crate::limits::{check_instance, validator_with_limits, LimitExceeded, ValidationLimits};
+use crate::redact::{redact_report, RedactionOptions};
+use crate::report::ValidationReport;
+use crate::util::escape_pointer_segment;
+
+/// Validates the inputs against the schema, failing with every validation error.
+///
+/// The error quotes the values that failed, including any the schema marks as sensitive: use
+/// `validate_json_redacted` when it may be logged or returned to a client.
 pub fn validate_json(schema: &Value, inputs: &Value) -> Result<()> {
-    let validator =
-        validator_for(schema).map_err(|err| anyhow!("Invalid json schema, error: {err}"))?;
+    let report = validate_json_report(schema, inputs, None)?;
 
-    let validation = validator.validate(inputs);
-
-    if validation.is_err() {
-        let error_msg = validator
-            .iter_errors(inputs)
-            .map(|error_instance| {
-                format!(
-                    "Validation Error [{}]. Schema Path [{}]. Instance Path [{}]. Instance: {}",
-                    error_instance,
-                    error_instance.schema_path,
-                    error_instance.instance_path,
-                    serde_json::to_string_pretty(&error_instance.instance).unwrap()
-                )
-            })
-            .reduce(|total_errors, error_line| total_errors + ", " + error_line.as_str())
-            .unwrap_or("Missing Error".to_string());
+    if !report.is_valid() {
+        return Err(validation_error(&report));
+    };
+
+    Ok(())
+}
+
+/// Validates the inputs like `validate_json`, but with the values the schema marks as sensitive
+/// redacted from the error, so that it can be logged.
+///
+/// # Arguments
+///
+/// * `schema`: A reference to the JSON schema `Value`.
+/// * `inputs`: The JSON value to validate.
+/// * `options`: Which values are sensitive and how to redact them.
+///
+pub fn validate_json_redacted(
+    schema: &Value,
+    inputs: &Value,
+    options: &RedactionOptions,
+) -> Result<()> {
+    let report = validate_json_report(schema, inputs, None)?;
+
+    if !report.is_valid() {
+        let inspector = SchemaInspector::new(schema)?;
+        let report = redact_report(&inspector, inputs, &report, options)?;
+        return Err(validation_error(&report));
+    }
 
-        bail!("Json failed validation, error(s): {error_msg}")
+    Ok(())
+}
+
+/// Validates the inputs like `validate_json`, but first checks the inputs and the schema's regexes
+/// against the limits, and gives up if validation runs past the timeout. An exceeded limit is
+/// returned as a `LimitExceeded` error, which can be told apart with `downcast_ref`.
+///
+/// With a timeout, validation runs on its own thread, which is left to finish in the background
+/// when it times out. See `ValidationLimits` for what that costs.
+///
+/// # Arguments
+///
+/// * `schema`: A reference to the JSON schema `Value`.
+/// * `inputs`: The JSON value to validate.
+/// * `limits`: The limits to enforce.
+///
+pub fn validate_json_with_limits(
+    schema: &Value,
+    inputs: &Value,
+    limits: &ValidationLimits,
+) -> Result<()> {
+    let validator = validator_with_limits(schema, limits)?;
+    check_instance(inputs, limits)?;
+
+    let report = match limits.timeout {
+        Some(timeout) => {
+            let running = RunningValidation::start(limits.max_timed_validations)?;
+            let (sender, receiver) = mpsc::channel();
+            let inputs = inputs.clone();
+            thread::spawn(move || {
+                let _running = running;
+                let _ = sender.send(ValidationReport::collect(&validator, &inputs, None));
+            });
+            receiver
+                .recv_timeout(timeout)
+                .map_err(|_| LimitExceeded::Timeout { limit: timeout })?
+        }
+        None => ValidationReport::collect(&validator, inputs, None),
     };
 
+    if !report.is_valid() {
+        return Err(validation_error(&report));
+    }
+
     Ok(())
 }
 
+/// How many validations with a timeout are running, including those that have timed out.
+static RUNNING_VALIDATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a validation in `RUNNING_VALIDATIONS` until it's dropped.
+struct RunningValidation;
+
+impl RunningValidation {
+    fn start(limit: Option<usize>) -> Result<Self, LimitExceeded> {
+        RUNNING_VALIDATIONS
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| match limit {
+                Some(limit) if running >= limit => None,
+                _ => Some(running + 1),
+            })
+            .map(|_| RunningValidation)
+            .map_err(|_| LimitExceeded::Busy {
+                limit: limit.unwrap_or_default(),
+            })
+    }
+}
+
+impl Drop for RunningValidation {
+    fn drop(&mut self) {
+        RUNNING_VALIDATIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn validation_error(report: &ValidationReport) -> anyhow::Error {
+    let error_msg = report
+        .issues
+        .iter()
+        .map(|issue| {
+            format!(
+                "Validation Error [{}]. Schema Path [{}]. Instance Path [{}]. Instance: {}",
+                issue.message,
+                issue.schema_path,
+                issue.instance_path,
+                serde_json::to_string_pretty(&issue.value).unwrap()
+            )
+        })
+        .reduce(|total_errors, error_line| total_errors + ", " + error_line.as_str())
+        .unwrap_or("Missing Error".to_string());
+
+    anyhow!("Json failed validation, error(s): {error_msg}")
+}
+
+/// Validates the inputs against the schema, returning every failure as a structured issue.
+///
+/// Unlike `validate_json`, a failed validation is not an error: the returned report lists the
+/// issues found and is empty when the inputs are valid. An error is only returned when the
+/// schema itself is invalid.
+///
+/// Issues carry the values that failed, in `value` and quoted in `message`, including any the
+/// schema marks as sensitive: pass the report through `redact_report`, or use
+/// `validate_json_redacted`, before it's logged or returned to a client.
+///
+/// # Arguments
+///
+/// * `schema`: A reference to the JSON schema `Value`.
+/// * `inputs`: The JSON value to validate.
+/// * `max_errors`: The maximum number of issues to collect, or `None` to collect them all.
+///
+pub fn validate_json_report(
+    schema: &Value,
+    inputs: &Value,
+    max_errors: Option<usize>,
+) -> Result<ValidationReport> {
+    let validator =
+        validator_for(schema).map_err(|err| anyhow!("Invalid json schema, error: {err}"))?;
+
+    Ok(ValidationReport::collect(&validator, inputs, max_errors))
+}
+
 /// Merge two json object into a single object with combined keys.
 /// Where two objects share a key, the second value wins.
 /// If the values passed aren't objects, returns an error.
+///
+/// This is a shallow merge: nested objects are replaced wholesale rather than merged. See
+/// `deep_merge_json_objects` for a recursive merge.
 pub fn merge_json_objects(a: Value, b: Value) -> Result<Value> {
+    let (a, b) = require_objects(a, b)?;
+    let merged_map = a.into_iter().chain(b).collect();
+
+    Ok(Value::Object(merged_map))
+}
+
+/// How arrays found at the same path in both objects are combined by a deep merge.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ArrayMergeStrategy {
+    /// The second array replaces the first.
+    #[default]
+    Replace,
+    /// The items of the second array are appended to the first.
+    Append,
+    /// Items at the same index are deep merged, with any extra items kept.
+    MergeByIndex,
+    /// Object items sharing the same value for the named field are deep merged. Items without a
+    /// match in the first array are appended.
+    MergeByKey(String),
+}
+
+/// What a `null` in the second object does to the corresponding key in the first.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NullMergeStrategy {
+    /// The key is set to `null`.
+    #[default]
+    Set,
+    /// The key is removed from the merged object.
+    Delete,
+}
+
+/// The outcome chosen by a conflict handler for two differing values at the same path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictResolution {
+    UseFirst,
+    UseSecond,
+    Use(Value),
+}
+
+/// Called with the JSON pointer and the first and second values wherever the two objects hold
+/// differing values that can't be merged recursively.
+pub type ConflictHandler = dyn Fn(&str, &Value, &Value) -> ConflictResolution;
+
+/// Options controlling `deep_merge_json_objects`.
+#[derive(Default)]
+pub struct DeepMergeOptions {
+    pub arrays: ArrayMergeStrategy,
+    pub nulls: NullMergeStrategy,
+    /// Decides conflicting values. Without a handler, the second value wins.
+    pub on_conflict: Option<Box<ConflictHandler>>,
+}
+
+/// Recursively merge two json objects into a single object.
+///
+/// Objects found at the same key are merged key by key rather than replaced, arrays are combined
+/// according to `options.arrays` and a `null` in the second object is handled according to
+/// `options.nulls`. Any other differing values are conflicts: the second value wins unless
+/// `options.on_conflict` decides otherwise.
+/// If the values passed aren't objects, returns an error.
+pub fn deep_merge_json_objects(a: Value, b: Value, options: &DeepMergeOptions) -> Result<Value> {
+    let (a, b) = require_objects(a, b)?;
+
+    Ok(Value::Object(deep_merge_maps(a, b, "", options)))
+}
+
+fn deep_merge_maps(
+    mut a: Map<String, Value>,
+    b: Map<String, Value>,
+    path: &str,
+    options: &DeepMergeOptions,
+) -> Map<String, Value> {
+    for (key, b_value) in b {
+        let child_path = format!("{path}/{}", escape_pointer_segment(&key));
+
+        if b_value.is_null() && options.nulls == NullMergeStrategy::Delete {
+            a.remove(&key);
+            continue;
+        }
+
+        let merged = match a.remove(&key) {
+            Some(a_value) => deep_merge_values(a_value, b_value, &child_path, options),
+            None => b_value,
+        };
+        a.insert(key, merged);
+    }
+
+    a
+}
+
+fn deep_merge_values(a: Value, b: Value, path: &str, options: &DeepMergeOptions) -> Value {
     match (a, b) {
-        (Value::Object(a), Value::Object(b)) => {
-            let merged_map = a.into_iter().chain(b).collect();
+        (Value::Object(a), Value::Object(b)) => Value::Object(deep_merge_maps(a, b, path, options)),
+        (Value::Array(a), Value::Array(b)) if options.arrays != ArrayMergeStrategy::Replace => {
+            Value::Array(deep_merge_arrays(a, b, path, options))
+        }
+        (a, b) if a == b => b,
+        (a, b) => match &options.on_conflict {
+            Some(on_conflict) => match on_conflict(path, &a, &b) {
+                ConflictResolution::UseFirst => a,
+                ConflictResolution::UseSecond => b,
+                ConflictResolution::Use(value) => value,
+            },
+            None => b,
+        },
+    }
+}
 
-            Ok(Value::Object(merged_map))
+fn deep_merge_arrays(
+    mut a: Vec<Value>,
+    b: Vec<Value>,
+    path: &str,
+    options: &DeepMergeOptions,
+) -> Vec<Value> {
+    match &options.arrays {
+        ArrayMergeStrategy::Replace => b,
+        ArrayMergeStrategy::Append => {
+            a.extend(b);
+            a
+        }
+        ArrayMergeStrategy::MergeByIndex => {
+            let mut b = b.into_iter();
+            let mut merged: Vec<Value> = a
+                .into_iter()
+                .enumerate()
+                .map(|(index, a_item)| match b.next() {
+                    Some(b_item) => {
+                        deep_merge_values(a_item, b_item, &format!("{path}/{index}"), options)
+                    }
+                    None => a_item,
+                })
+                .collect();
+            merged.extend(b);
+            merged
         }
+        ArrayMergeStrategy::MergeByKey(field) => {
+            for b_item in b {
+                let matching_index = b_item
+                    .get(field)
+                    .and_then(|b_key| a.iter().position(|a_item| a_item.get(field) == Some(b_key)));
+
+                match matching_index {
+                    Some(index) => {
+                        let a_item = std::mem::take(&mut a[index]);
+                        a[index] =
+                            deep_merge_values(a_item, b_item, &format!("{path}/{index}"), options);
+                    }
+                    None => a.push(b_item),
+                }
+            }
+            a
+        }
+    }
+}
+
+fn require_objects(a: Value, b: Value) -> Result<(Map<String, Value>, Map<String, Value>)> {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => Ok((a, b)),
         (Value::Object(_), b) => {
             let b_prettified = serde_json::to_string_pretty(&b)?;
             bail!("value required to be object to merge. Instead got {b_prettified}");
@@ -62,7 +354,13 @@ mod tests {
     use assertables::assert_starts_with;
     use serde_json::json;
 
-    use super::{merge_json_objects, validate_json};
+    use super::{
+        deep_merge_json_objects, merge_json_objects, validate_json, validate_json_redacted,
+        validate_json_report, validate_json_with_limits, ArrayMergeStrategy, ConflictResolution,
+        DeepMergeOptions, NullMergeStrategy,
+    };
+    use crate::limits::{LimitExceeded, ValidationLimits};
+    use crate::redact::RedactionOptions;
 
     #[test]
     fn test_validate_json_errors_messages_contain_paths() {
@@ -91,6 +389,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn validate_json_redacted_hides_sensitive_values() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "iban": { "type": "string", "format": "iban", "minLength": 15 }
+            }
+        });
+        let inputs = json!({ "iban": "GB82WEST" });
+
+        let err = validate_json_redacted(&schema, &inputs, &RedactionOptions::default())
+            .unwrap_err()
+            .to_string();
+
+        assert!(validate_json(&schema, &inputs)
+            .unwrap_err()
+            .to_string()
+            .contains("GB82WEST"));
+        assert!(!err.contains("GB82WEST"), "{err}");
+        assert_starts_with!(
+            err,
+            r#"Json failed validation, error(s): Validation Error ["[REDACTED]" is shorter than 15 characters]. Schema Path [/properties/iban/minLength]. Instance Path [/iban]."#
+        );
+    }
+
+    #[test]
+    fn validate_json_with_limits_rejects_oversized_inputs_before_validating() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "notes": { "type": "array", "items": { "type": "string" } } }
+        });
+        let limits = ValidationLimits {
+            max_array_length: Some(2),
+            ..ValidationLimits::default()
+        };
+
+        assert!(
+            validate_json_with_limits(&schema, &json!({ "notes": ["a", "b"] }), &limits).is_ok()
+        );
+        let err = validate_json_with_limits(&schema, &json!({ "notes": ["a", "b", "c"] }), &limits)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded::ArrayLength {
+                instance_path: "/notes".to_string(),
+                length: 3,
+                limit: 2
+            })
+        );
+        assert_starts_with!(
+            validate_json_with_limits(&schema, &json!({ "notes": [1] }), &limits)
+                .unwrap_err()
+                .to_string(),
+            "Json failed validation, error(s): Validation Error [1 is not of type \"string\"]."
+        );
+    }
+
+    #[test]
+    fn validate_json_with_limits_times_out() {
+        let schema = json!({ "type": "array", "items": { "type": "string", "pattern": "^x" } });
+        let inputs = json!(vec!["x".repeat(100); 100_000]);
+        let limits = ValidationLimits {
+            timeout: Some(std::time::Duration::from_nanos(1)),
+            ..ValidationLimits::default()
+        };
+
+        let err = validate_json_with_limits(&schema, &inputs, &limits).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<LimitExceeded>(),
+            Some(LimitExceeded::Timeout { .. })
+        ));
+    }
+
+    #[test]
+    fn validate_json_with_limits_caps_the_validations_running_at_once() {
+        let schema = json!({ "type": "string" });
+        let limits = ValidationLimits {
+            max_timed_validations: Some(0),
+            ..ValidationLimits::default()
+        };
+
+        let err = validate_json_with_limits(&schema, &json!("a"), &limits).unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded::Busy { limit: 0 })
+        );
+        let untimed = ValidationLimits {
+            timeout: None,
+            ..limits
+        };
+        assert!(validate_json_with_limits(&schema, &json!("a"), &untimed).is_ok());
+    }
+
     #[test]
     fn validate_workflow_input_accepts_valid_inputs() {
         let schema = json!({
@@ -133,6 +526,53 @@ mod tests {
         )
     }
 
+    #[test]
+    fn validate_json_report_lists_every_failing_field() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "x": { "type": "number" },
+                "y": { "type": "number" }
+            }
+        });
+        let inputs = json!({
+            "x": "wibble",
+            "y": "wobble"
+        });
+
+        let report = validate_json_report(&schema, &inputs, None).unwrap();
+
+        let instance_paths: Vec<&str> = report
+            .issues
+            .iter()
+            .map(|issue| issue.instance_path.as_str())
+            .collect();
+        assert_eq!(instance_paths, vec!["/x", "/y"]);
+    }
+
+    #[test]
+    fn validate_json_report_caps_the_number_of_issues() {
+        let schema = json!({ "items": { "type": "string" } });
+        let inputs = json!([1, 2, 3, 4]);
+
+        let report = validate_json_report(&schema, &inputs, Some(2)).unwrap();
+
+        assert_eq!(report.issues.len(), 2);
+        assert!(report.truncated);
+    }
+
+    #[test]
+    fn validate_json_report_fails_for_an_invalid_schema() {
+        let schema = json!({ "type": "wibble" });
+
+        let result = validate_json_report(&schema, &json!({}), None);
+
+        assert_starts_with!(
+            result.unwrap_err().to_string(),
+            "Invalid json schema, error:"
+        );
+    }
+
     #[test]
     fn merge_json_objects_returns_failure_when_first_value_is_not_an_object() {
         let obj1 = json!("foo");
@@ -205,4 +645,115 @@ mod tests {
             Config::new(CompareMode::Strict)
         );
     }
+
+    #[test]
+    fn merge_json_objects_replaces_nested_objects() {
+        let result = merge_json_objects(json!({ "a": { "x": 1 } }), json!({ "a": { "y": 2 } }))
+            .expect("Two objects should have merged successfully");
+
+        assert_eq!(result, json!({ "a": { "y": 2 } }));
+    }
+
+    #[test]
+    fn deep_merge_json_objects_merges_nested_objects() {
+        let result = deep_merge_json_objects(
+            json!({ "a": { "x": 1, "nested": { "p": true } }, "b": 1 }),
+            json!({ "a": { "y": 2, "nested": { "q": false } } }),
+            &DeepMergeOptions::default(),
+        )
+        .expect("Two objects should have merged successfully");
+
+        assert_json_matches!(
+            result,
+            json!({ "a": { "x": 1, "y": 2, "nested": { "p": true, "q": false } }, "b": 1 }),
+            Config::new(CompareMode::Strict)
+        );
+    }
+
+    #[test]
+    fn deep_merge_json_objects_returns_failure_when_a_value_is_not_an_object() {
+        let result = deep_merge_json_objects(json!({}), json!([1]), &DeepMergeOptions::default());
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "value required to be object to merge. Instead got [\n  1\n]"
+        )
+    }
+
+    #[test]
+    fn deep_merge_json_objects_combines_arrays_per_strategy() {
+        let a = json!({ "list": [{ "id": 1, "x": 1 }, { "id": 2, "x": 2 }] });
+        let b = json!({ "list": [{ "id": 2, "y": 2 }, { "id": 3, "y": 3 }] });
+        let merge_with = |arrays| {
+            let options = DeepMergeOptions {
+                arrays,
+                ..Default::default()
+            };
+            deep_merge_json_objects(a.clone(), b.clone(), &options).unwrap()["list"].clone()
+        };
+
+        assert_eq!(
+            merge_with(ArrayMergeStrategy::Replace),
+            json!([{ "id": 2, "y": 2 }, { "id": 3, "y": 3 }])
+        );
+        assert_eq!(
+            merge_with(ArrayMergeStrategy::Append),
+            json!([{ "id": 1, "x": 1 }, { "id": 2, "x": 2 }, { "id": 2, "y": 2 }, { "id": 3, "y": 3 }])
+        );
+        assert_eq!(
+            merge_with(ArrayMergeStrategy::MergeByIndex),
+            json!([{ "id": 2, "x": 1, "y": 2 }, { "id": 3, "x": 2, "y": 3 }])
+        );
+        assert_eq!(
+            merge_with(ArrayMergeStrategy::MergeByKey("id".to_string())),
+            json!([{ "id": 1, "x": 1 }, { "id": 2, "x": 2, "y": 2 }, { "id": 3, "y": 3 }])
+        );
+    }
+
+    #[test]
+    fn deep_merge_json_objects_handles_nulls_per_strategy() {
+        let a = json!({ "a": { "x": 1, "y": 2 } });
+        let b = json!({ "a": { "x": null } });
+
+        let set =
+            deep_merge_json_objects(a.clone(), b.clone(), &DeepMergeOptions::default()).unwrap();
+        let delete = deep_merge_json_objects(
+            a,
+            b,
+            &DeepMergeOptions {
+                nulls: NullMergeStrategy::Delete,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(set, json!({ "a": { "x": null, "y": 2 } }));
+        assert_eq!(delete, json!({ "a": { "y": 2 } }));
+    }
+
+    #[test]
+    fn deep_merge_json_objects_calls_conflict_handler_with_path() {
+        let options = DeepMergeOptions {
+            on_conflict: Some(Box::new(|path, a, b| match path {
+                "/a/keep" => ConflictResolution::UseFirst,
+                "/a/sum" => {
+                    ConflictResolution::Use(json!(a.as_i64().unwrap() + b.as_i64().unwrap()))
+                }
+                _ => ConflictResolution::UseSecond,
+            })),
+            ..Default::default()
+        };
+
+        let result = deep_merge_json_objects(
+            json!({ "a": { "keep": "old", "sum": 1, "other": "old", "same": 1 } }),
+            json!({ "a": { "keep": "new", "sum": 2, "other": "new", "same": 1 } }),
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            json!({ "a": { "keep": "old", "sum": 3, "other": "new", "same": 1 } })
+        );
+    }
 }
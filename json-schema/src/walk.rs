@@ -0,0 +1,489 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use jsonschema::{Draft, Registry, Validator};
+use referencing::{Resolver, ResourceRef};
+use serde_json::Value;
+
+use crate::registry::OfflineRetriever;
+use crate::util::escape_pointer_segment;
+
+/// How `anyOf`, `oneOf` and `if`/`then`/`else` branches are treated when expanding a schema.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BranchMode {
+    /// Every branch is included, marked as conditional.
+    All,
+    /// Only the branches the instance is valid against are included.
+    Matching,
+    /// No branches are included.
+    Skip,
+}
+
+/// A sub-schema reached while walking a schema, along with what's needed to resolve its
+/// references.
+#[derive(Clone)]
+pub(crate) struct SchemaNode<'r> {
+    pub schema: &'r Value,
+    pub resolver: Resolver<'r>,
+    pub draft: Draft,
+    /// Keyword path from the root schema, including any `$ref`s followed, as jsonschema reports
+    /// schema paths.
+    pub schema_path: String,
+    /// Set when the node only applies under some `anyOf`, `oneOf` or `if` branch.
+    pub conditional: bool,
+}
+
+impl<'r> SchemaNode<'r> {
+    pub fn get(&self, keyword: &str) -> Option<&'r Value> {
+        self.schema.get(keyword)
+    }
+
+    /// The types the node allows, whether `type` is a single string or an array.
+    pub fn types(&self) -> Vec<&'r str> {
+        match self.get("type") {
+            Some(Value::String(single)) => vec![single.as_str()],
+            Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The sub-schemas under an array-valued keyword such as `oneOf`, unexpanded.
+    pub fn subschemas(&self, keyword: &str) -> Result<Vec<SchemaNode<'r>>> {
+        let Some(Value::Array(schemas)) = self.get(keyword) else {
+            return Ok(Vec::new());
+        };
+
+        schemas
+            .iter()
+            .enumerate()
+            .map(|(index, schema)| self.child(&[keyword, &index.to_string()], schema))
+            .collect()
+    }
+
+    /// The node for a sub-schema reached through the keyword path `segments`.
+    pub fn child(&self, segments: &[&str], schema: &'r Value) -> Result<SchemaNode<'r>> {
+        let draft = self.draft.detect(schema).unwrap_or(self.draft);
+        let resolver = self
+            .resolver
+            .in_subresource(ResourceRef::new(schema, draft))
+            .map_err(|err| anyhow!("Invalid $id in schema, error: {err}"))?;
+        let mut schema_path = self.schema_path.clone();
+        for segment in segments {
+            schema_path.push('/');
+            schema_path.push_str(&escape_pointer_segment(segment));
+        }
+
+        Ok(SchemaNode {
+            schema,
+            resolver,
+            draft,
+            schema_path,
+            conditional: self.conditional,
+        })
+    }
+
+    fn conditional(mut self) -> Self {
+        self.conditional = true;
+        self
+    }
+}
+
+/// Validators compiled for sub-schemas, keyed by their location in the registry and base URI.
+/// `None` records a sub-schema that couldn't be compiled on its own.
+type ValidatorCache = HashMap<(*const Value, String), Option<Arc<Validator>>>;
+
+/// Compiled `pattern` and `patternProperties` regexes, keyed by pattern. `None` records a pattern
+/// that couldn't be compiled.
+type PatternCache = HashMap<String, Option<Arc<fancy_regex::Regex>>>;
+
+/// Walks a schema, resolving references against a `referencing` registry.
+pub(crate) struct SchemaWalker<'r> {
+    registry: &'r Registry,
+    validators: RefCell<ValidatorCache>,
+    patterns: RefCell<PatternCache>,
+}
+
+impl<'r> SchemaWalker<'r> {
+    pub fn new(registry: &'r Registry) -> Self {
+        SchemaWalker {
+            registry,
+            validators: RefCell::new(HashMap::new()),
+            patterns: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The node for the schema registered at the URI.
+    pub fn root(&self, uri: &str) -> Result<SchemaNode<'r>> {
+        let resolver = self.registry.try_resolver(uri)?;
+        let resolved = resolver
+            .lookup(uri)
+            .map_err(|err| anyhow!("Unable to resolve schema {uri}, error: {err}"))?;
+        let (schema, resolver, draft) = resolved.into_inner();
+        let draft = draft.detect(schema).unwrap_or(draft);
+
+        Ok(SchemaNode {
+            schema,
+            resolver,
+            draft,
+            schema_path: String::new(),
+            conditional: false,
+        })
+    }
+
+    /// Whether the instance is valid against the node.
+    pub fn is_valid(&self, node: &SchemaNode<'r>, instance: &Value) -> bool {
+        self.validator(node)
+            .is_some_and(|validator| validator.is_valid(instance))
+    }
+
+    /// Whether the value matches the regex, compiling each pattern only once per walk. A pattern
+    /// that can't be compiled matches nothing.
+    pub fn pattern_matches(&self, pattern: &str, value: &str) -> bool {
+        let regex = self
+            .patterns
+            .borrow_mut()
+            .entry(pattern.to_string())
+            .or_insert_with(|| fancy_regex::Regex::new(pattern).ok().map(Arc::new))
+            .clone();
+
+        regex.is_some_and(|regex| regex.is_match(value).unwrap_or(false))
+    }
+
+    /// A validator for the node, or `None` if it can't be compiled on its own.
+    pub fn validator(&self, node: &SchemaNode<'r>) -> Option<Arc<Validator>> {
+        let base_uri = node.resolver.base_uri().as_str().to_string();
+        let key = (node.schema as *const Value, base_uri.clone());
+
+        self.validators
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| {
+                // The sub-schema's base URI is already registered for the document it was found in,
+                // so its fragment references resolve against that document rather than itself.
+                jsonschema::options()
+                    .with_draft(node.draft)
+                    .with_registry(self.registry.clone())
+                    .with_retriever(OfflineRetriever)
+                    .with_base_uri(base_uri)
+                    .build(node.schema)
+                    .ok()
+                    .map(Arc::new)
+            })
+            .clone()
+    }
+
+    /// Expands the node into every schema that applies at its location: the node itself plus
+    /// whatever it pulls in through `$ref`, `allOf` and, depending on `mode`, its branches.
+    pub fn expand(
+        &self,
+        node: &SchemaNode<'r>,
+        instance: Option<&Value>,
+        mode: BranchMode,
+    ) -> Result<Vec<SchemaNode<'r>>> {
+        let mut expanded = Vec::new();
+        let mut visited = HashSet::new();
+        self.expand_into(node, instance, mode, &mut expanded, &mut visited)?;
+
+        Ok(expanded)
+    }
+
+    /// Expands every node, see `expand`.
+    pub fn expand_all(
+        &self,
+        nodes: &[SchemaNode<'r>],
+        instance: Option<&Value>,
+        mode: BranchMode,
+    ) -> Result<Vec<SchemaNode<'r>>> {
+        let mut expanded = Vec::new();
+        let mut visited = HashSet::new();
+        for node in nodes {
+            self.expand_into(node, instance, mode, &mut expanded, &mut visited)?;
+        }
+
+        Ok(expanded)
+    }
+
+    fn expand_into(
+        &self,
+        node: &SchemaNode<'r>,
+        instance: Option<&Value>,
+        mode: BranchMode,
+        expanded: &mut Vec<SchemaNode<'r>>,
+        visited: &mut HashSet<(*const Value, bool)>,
+    ) -> Result<()> {
+        if !node.schema.is_object()
+            || !visited.insert((node.schema as *const Value, node.conditional))
+        {
+            return Ok(());
+        }
+        expanded.push(node.clone());
+
+        for keyword in ["$ref", "$dynamicRef"] {
+            if let Some(reference) = node.get(keyword).and_then(Value::as_str) {
+                let target = self.follow(node, keyword, reference)?;
+                self.expand_into(&target, instance, mode, expanded, visited)?;
+            }
+        }
+        if node.get("$recursiveRef").is_some() {
+            let resolved = node
+                .resolver
+                .lookup_recursive_ref()
+                .map_err(|err| anyhow!("Unresolvable $recursiveRef, error: {err}"))?;
+            let target = self.resolved_node(node, "$recursiveRef", resolved)?;
+            self.expand_into(&target, instance, mode, expanded, visited)?;
+        }
+
+        if let Some(Value::Array(schemas)) = node.get("allOf") {
+            for (index, schema) in schemas.iter().enumerate() {
+                let child = node.child(&["allOf", &index.to_string()], schema)?;
+                self.expand_into(&child, instance, mode, expanded, visited)?;
+            }
+        }
+
+        for keyword in ["anyOf", "oneOf"] {
+            if let Some(Value::Array(schemas)) = node.get(keyword) {
+                for (index, schema) in schemas.iter().enumerate() {
+                    let child = node.child(&[keyword, &index.to_string()], schema)?;
+                    if let Some(branch) = self.branch(child, instance, mode) {
+                        self.expand_into(&branch, instance, mode, expanded, visited)?;
+                    }
+                }
+            }
+        }
+
+        if let Some(condition) = node.get("if") {
+            let condition = node.child(&["if"], condition)?;
+            let branches: &[&str] = match (mode, instance) {
+                (BranchMode::Matching, Some(instance)) if self.is_valid(&condition, instance) => {
+                    &["then"]
+                }
+                (BranchMode::Matching, _) => &["else"],
+                (BranchMode::All, _) => &["then", "else"],
+                (BranchMode::Skip, _) => &[],
+            };
+            for keyword in branches {
+                if let Some(schema) = node.get(keyword) {
+                    let mut child = node.child(&[keyword], schema)?;
+                    child.conditional |= mode == BranchMode::All;
+                    self.expand_into(&child, instance, mode, expanded, visited)?;
+                }
+            }
+        }
+
+        if let Some(Value::Object(dependents)) = node.get("dependentSchemas") {
+            for (property, schema) in dependents {
+                let present = instance
+                    .and_then(Value::as_object)
+                    .is_some_and(|object| object.contains_key(property));
+                let child = node.child(&["dependentSchemas", property], schema)?;
+                let child = match mode {
+                    BranchMode::All => Some(child.conditional()),
+                    BranchMode::Matching if present => Some(child),
+                    _ => None,
+                };
+                if let Some(child) = child {
+                    self.expand_into(&child, instance, mode, expanded, visited)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn branch(
+        &self,
+        child: SchemaNode<'r>,
+        instance: Option<&Value>,
+        mode: BranchMode,
+    ) -> Option<SchemaNode<'r>> {
+        match (mode, instance) {
+            (BranchMode::All, _) => Some(child.conditional()),
+            (BranchMode::Matching, Some(instance)) if self.is_valid(&child, instance) => {
+                Some(child)
+            }
+            _ => None,
+        }
+    }
+
+    /// The expanded branches of each `oneOf` and `anyOf` of the nodes that the value matches no
+    /// branch of. `BranchMode::Matching` leaves these out, though one of them was likely meant.
+    pub fn unmatched_branches(
+        &self,
+        nodes: &[SchemaNode<'r>],
+        value: &Value,
+    ) -> Result<Vec<SchemaNode<'r>>> {
+        let mut branches = Vec::new();
+
+        for node in nodes {
+            for keyword in ["oneOf", "anyOf"] {
+                let subschemas = node.subschemas(keyword)?;
+                if subschemas
+                    .iter()
+                    .all(|branch| !self.is_valid(branch, value))
+                {
+                    branches.extend(self.expand_all(&subschemas, Some(value), BranchMode::All)?);
+                }
+            }
+        }
+
+        Ok(branches)
+    }
+
+    /// Resolves a reference made from the node.
+    pub fn follow(
+        &self,
+        node: &SchemaNode<'r>,
+        keyword: &str,
+        reference: &str,
+    ) -> Result<SchemaNode<'r>> {
+        let resolved = node
+            .resolver
+            .lookup(reference)
+            .map_err(|err| anyhow!("Unresolvable reference \"{reference}\", error: {err}"))?;
+
+        self.resolved_node(node, keyword, resolved)
+    }
+
+    fn resolved_node(
+        &self,
+        node: &SchemaNode<'r>,
+        keyword: &str,
+        resolved: referencing::Resolved<'r>,
+    ) -> Result<SchemaNode<'r>> {
+        let (schema, resolver, draft) = resolved.into_inner();
+        let draft = draft.detect(schema).unwrap_or(draft);
+        let resolver = resolver
+            .in_subresource(ResourceRef::new(schema, draft))
+            .map_err(|err| anyhow!("Invalid $id in schema, error: {err}"))?;
+
+        Ok(SchemaNode {
+            schema,
+            resolver,
+            draft,
+            schema_path: format!("{}/{keyword}", node.schema_path),
+            conditional: node.conditional,
+        })
+    }
+
+    /// The unexpanded schemas that apply to the property of an object described by the nodes,
+    /// from `properties`, `patternProperties` or `additionalProperties`.
+    pub fn property_nodes(
+        &self,
+        nodes: &[SchemaNode<'r>],
+        property: &str,
+    ) -> Result<Vec<SchemaNode<'r>>> {
+        let mut children = Vec::new();
+
+        for node in nodes {
+            let mut matched = false;
+
+            if let Some(schema) = node.get("properties").and_then(|p| p.get(property)) {
+                children.push(node.child(&["properties", property], schema)?);
+                matched = true;
+            }
+            if let Some(Value::Object(patterns)) = node.get("patternProperties") {
+                for (pattern, schema) in patterns {
+                    if self.pattern_matches(pattern, property) {
+                        children.push(node.child(&["patternProperties", pattern], schema)?);
+                        matched = true;
+                    }
+                }
+            }
+            if !matched {
+                if let Some(schema) = node.get("additionalProperties") {
+                    children.push(node.child(&["additionalProperties"], schema)?);
+                }
+            }
+        }
+
+        Ok(children)
+    }
+
+    /// The unexpanded schemas that apply to the item at the index of an array described by the
+    /// nodes, from `prefixItems`, `items` or `additionalItems`.
+    pub fn item_nodes(
+        &self,
+        nodes: &[SchemaNode<'r>],
+        index: usize,
+    ) -> Result<Vec<SchemaNode<'r>>> {
+        let mut children = Vec::new();
+
+        for node in nodes {
+            let (tuple_keyword, rest_keyword) = match node.get("prefixItems") {
+                Some(_) => ("prefixItems", "items"),
+                None if node.get("items").is_some_and(Value::is_array) => {
+                    ("items", "additionalItems")
+                }
+                None => ("prefixItems", "items"),
+            };
+            let tuple = node.get(tuple_keyword).and_then(Value::as_array);
+
+            match tuple.and_then(|tuple| tuple.get(index)) {
+                Some(schema) => {
+                    children.push(node.child(&[tuple_keyword, &index.to_string()], schema)?)
+                }
+                None => {
+                    if let Some(schema) = node.get(rest_keyword).filter(|schema| !schema.is_array())
+                    {
+                        children.push(node.child(&[rest_keyword], schema)?);
+                    }
+                }
+            }
+        }
+
+        Ok(children)
+    }
+}
+
+/// Walks the instance alongside the schema, calling the visitor with the walker, the expanded
+/// schema nodes that apply to each value and the value's JSON pointer, before visiting its
+/// children. Values that no schema describes are not visited.
+pub(crate) fn visit_instance<'r, F>(
+    walker: &SchemaWalker<'r>,
+    nodes: &[SchemaNode<'r>],
+    instance: &mut Value,
+    path: &str,
+    mode: BranchMode,
+    visitor: &mut F,
+) -> Result<()>
+where
+    F: FnMut(&SchemaWalker<'r>, &[SchemaNode<'r>], &mut Value, &str) -> Result<()>,
+{
+    let expanded = walker.expand_all(nodes, Some(instance), mode)?;
+    visitor(walker, &expanded, instance, path)?;
+
+    match instance {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                let children = walker.property_nodes(&expanded, key)?;
+                if !children.is_empty() {
+                    let path = format!("{path}/{}", escape_pointer_segment(key));
+                    visit_instance(walker, &children, value, &path, mode, visitor)?;
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                let children = walker.item_nodes(&expanded, index)?;
+                if !children.is_empty() {
+                    let path = format!("{path}/{index}");
+                    visit_instance(walker, &children, item, &path, mode, visitor)?;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Whether the value matches the regex, compiling it afresh. Use `SchemaWalker::pattern_matches`
+/// when matching repeatedly during a walk.
+pub(crate) fn pattern_matches(pattern: &str, value: &str) -> bool {
+    fancy_regex::Regex::new(pattern)
+        .ok()
+        .and_then(|regex| regex.is_match(value).ok())
+        .unwrap_or(false)
+}